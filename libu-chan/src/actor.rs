@@ -0,0 +1,372 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flume::{RecvTimeoutError, SendError, Sender, bounded};
+use libu_trait::PanicMsg;
+
+use crate::chan::*;
+
+/// A thread that owns some state and handles one message at a time.
+///
+/// Start one with [`spawn`], or with a [`Supervisor`] to restart it
+/// after a panic, and talk to it through its [`Addr`].
+///
+/// # Example
+///
+/// ```rust
+/// use libu_chan::{Actor, spawn};
+///
+/// struct Counter(u64);
+///
+/// impl Actor for Counter {
+///   type Msg = u64;
+///   type Reply = u64;
+///
+///   fn handle(&mut self, n: u64) -> u64 {
+///     self.0 += n;
+///     self.0
+///   }
+/// }
+///
+/// let counter = spawn(Counter(0));
+/// counter.send(1).unwrap();
+/// assert_eq!(counter.ask(2), Ok(3));
+///
+/// counter.stop();
+/// counter.wait();
+/// ```
+pub trait Actor: Send + 'static {
+  type Msg: Send + 'static;
+  type Reply: Send + 'static;
+
+  fn handle(&mut self, msg: Self::Msg) -> Self::Reply;
+
+  /// Called on the actor's thread before the first message, and again
+  /// after every restart.
+  fn started(&mut self) {}
+
+  /// Called on the actor's thread once it stops normally. Not called
+  /// after a panic.
+  fn stopped(&mut self) {}
+}
+
+/// Lifecycle of an actor, from [`Addr::state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActorState {
+  Running,
+  /// Stopped with [`Addr::stop`], or because every `Addr` was dropped.
+  Stopped,
+  /// `started` or `handle` panicked and the actor was not restarted.
+  Panicked,
+}
+
+/// Why [`Addr::ask`] got no reply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActorError {
+  /// The actor stopped before handling the message.
+  Stopped,
+  /// The handler panicked on this message.
+  Panicked,
+  /// No reply in time. The message may still be handled later.
+  Timeout,
+}
+
+impl std::fmt::Display for ActorError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Stopped => write!(f, "actor stopped"),
+      Self::Panicked => write!(f, "actor panicked while handling the message"),
+      Self::Timeout => write!(f, "actor did not reply in time"),
+    }
+  }
+}
+
+impl std::error::Error for ActorError {}
+
+type Reply<A> = Sender<Result<<A as Actor>::Reply, ActorError>>;
+
+/// A message and, for `ask`, where to send the reply.
+type Mail<A> = (<A as Actor>::Msg, Option<Reply<A>>);
+
+struct ActorShared {
+  state: Mutex<ActorState>,
+  changed: Condvar,
+  restarts: AtomicU32,
+}
+
+impl ActorShared {
+  fn lock(&self) -> MutexGuard<'_, ActorState> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  fn finish(&self, state: ActorState) {
+    *self.lock() = state;
+    self.changed.notify_all();
+  }
+}
+
+/// Handle to a running actor. Cheap to clone; the actor stops once it
+/// has handled everything sent before every clone was dropped.
+pub struct Addr<A: Actor> {
+  /// Outer end of the mailbox `channel()`; nothing is sent back on it.
+  chan: Arc<Chan<Mail<A>, ()>>,
+  shared: Arc<ActorShared>,
+}
+
+impl<A: Actor> Addr<A> {
+  /// Queue `msg`, discarding the reply. Fails, handing `msg` back, once
+  /// the actor has stopped or [`stop`](Self::stop) was called.
+  pub fn send(&self, msg: A::Msg) -> Result<(), SendError<A::Msg>> {
+    self
+      .chan
+      .send((msg, None))
+      .map_err(|SendError((msg, _))| SendError(msg))
+  }
+
+  /// Queue `msg` and block until the actor replies.
+  pub fn ask(&self, msg: A::Msg) -> Result<A::Reply, ActorError> {
+    let (tx, rx) = bounded(1);
+    self
+      .chan
+      .send((msg, Some(tx)))
+      .map_err(|_| ActorError::Stopped)?;
+
+    rx.recv().unwrap_or(Err(ActorError::Stopped))
+  }
+
+  /// Like [`ask`](Self::ask), giving up after `timeout`.
+  pub fn ask_timeout(&self, msg: A::Msg, timeout: Duration) -> Result<A::Reply, ActorError> {
+    let (tx, rx) = bounded(1);
+    self
+      .chan
+      .send((msg, Some(tx)))
+      .map_err(|_| ActorError::Stopped)?;
+
+    match rx.recv_timeout(timeout) {
+      Ok(reply) => reply,
+      Err(RecvTimeoutError::Timeout) => Err(ActorError::Timeout),
+      Err(RecvTimeoutError::Disconnected) => Err(ActorError::Stopped),
+    }
+  }
+
+  /// Stop accepting messages. The actor handles what is already queued,
+  /// runs [`Actor::stopped`] and exits.
+  pub fn stop(&self) {
+    self.chan.close_send();
+  }
+
+  pub fn state(&self) -> ActorState {
+    *self.shared.lock()
+  }
+
+  /// Times a [`Supervisor`] has restarted the actor.
+  pub fn restarts(&self) -> u32 {
+    self.shared.restarts.load(Ordering::Relaxed)
+  }
+
+  /// Block until the actor is no longer running and return how it
+  /// ended.
+  pub fn wait(&self) -> ActorState {
+    let mut state = self.shared.lock();
+    while *state == ActorState::Running {
+      state = self
+        .shared
+        .changed
+        .wait(state)
+        .unwrap_or_else(|e| e.into_inner());
+    }
+
+    *state
+  }
+}
+
+impl<A: Actor> Clone for Addr<A> {
+  fn clone(&self) -> Self {
+    Self {
+      chan: self.chan.clone(),
+      shared: self.shared.clone(),
+    }
+  }
+}
+
+impl<A: Actor> std::fmt::Debug for Addr<A> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<Addr<A>>())
+  }
+}
+
+/// Restarts actors whose `started` or `handle` panicked, with fresh state
+/// from a factory, keeping their mailbox and every [`Addr`] valid.
+///
+/// An actor that needs more than `max_restarts` restarts within
+/// `within` is given up on and ends as [`ActorState::Panicked`].
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use libu_chan::{Actor, ActorError, Supervisor};
+///
+/// struct Parser;
+///
+/// impl Actor for Parser {
+///   type Msg = &'static str;
+///   type Reply = u32;
+///
+///   fn handle(&mut self, s: &'static str) -> u32 {
+///     s.parse().unwrap()
+///   }
+/// }
+///
+/// let parser = Supervisor::new(3, Duration::from_secs(1)).spawn(|| Parser);
+/// assert_eq!(parser.ask("oops"), Err(ActorError::Panicked));
+/// assert_eq!(parser.ask("42"), Ok(42));
+/// assert_eq!(parser.restarts(), 1);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Supervisor {
+  max_restarts: u32,
+  within: Duration,
+}
+
+impl Supervisor {
+  pub fn new(max_restarts: u32, within: Duration) -> Self {
+    Self {
+      max_restarts,
+      within,
+    }
+  }
+
+  /// Start an actor built by `factory`, calling it again for each
+  /// restart.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the actor thread cannot be spawned.
+  pub fn spawn<A, F>(&self, factory: F) -> Addr<A>
+  where
+    A: Actor,
+    F: FnMut() -> A + Send + 'static,
+  {
+    start(factory, Some(*self))
+  }
+}
+
+impl Default for Supervisor {
+  /// Three restarts within five seconds.
+  fn default() -> Self {
+    Self::new(3, Duration::from_secs(5))
+  }
+}
+
+/// Start `actor` on its own thread. If `started` or `handle` panics,
+/// the actor ends as [`ActorState::Panicked`]; use a [`Supervisor`] to
+/// restart it instead.
+///
+/// # Panics
+///
+/// Panics if the actor thread cannot be spawned.
+pub fn spawn<A: Actor>(actor: A) -> Addr<A> {
+  let mut actor = Some(actor);
+  start(
+    move || {
+      actor
+        .take()
+        .expect("unsupervised actors are never restarted")
+    },
+    None,
+  )
+}
+
+fn start<A, F>(mut factory: F, supervisor: Option<Supervisor>) -> Addr<A>
+where
+  A: Actor,
+  F: FnMut() -> A + Send + 'static,
+{
+  let (outer, inner) = channel::<Mail<A>, ()>();
+  let shared = Arc::new(ActorShared {
+    state: Mutex::new(ActorState::Running),
+    changed: Condvar::new(),
+    restarts: AtomicU32::new(0),
+  });
+
+  let state = shared.clone();
+  thread::Builder::new()
+    .name("libu-chan-actor".to_owned())
+    .spawn(move || {
+      let mut restarts = VecDeque::new();
+
+      loop {
+        match run(&mut factory, &inner) {
+          Ok(()) => return state.finish(ActorState::Stopped),
+          Err(panic) => report::<A>(panic),
+        }
+
+        let Some(supervisor) = supervisor else {
+          return state.finish(ActorState::Panicked);
+        };
+
+        // Keep only the restarts inside the window.
+        let now = Instant::now();
+        restarts.retain(|at| now.duration_since(*at) < supervisor.within);
+        if restarts.len() >= supervisor.max_restarts as usize {
+          return state.finish(ActorState::Panicked);
+        }
+        restarts.push_back(now);
+        state.restarts.fetch_add(1, Ordering::Relaxed);
+      }
+    })
+    .expect("failed to spawn actor thread");
+
+  Addr {
+    chan: Arc::new(outer),
+    shared,
+  }
+}
+
+/// One life of an actor: build it, then handle mail until the mailbox
+/// closes. Returns the panic payload if it died.
+fn run<A, F>(factory: &mut F, mailbox: &Chan<(), Mail<A>>) -> Result<(), Box<dyn Any + Send>>
+where
+  A: Actor,
+  F: FnMut() -> A,
+{
+  let mut actor = catch_unwind(AssertUnwindSafe(&mut *factory))?;
+  catch_unwind(AssertUnwindSafe(|| actor.started()))?;
+
+  for (msg, reply) in mailbox.iter() {
+    match catch_unwind(AssertUnwindSafe(|| actor.handle(msg))) {
+      Ok(resp) => {
+        if let Some(reply) = reply {
+          let _ = reply.send(Ok(resp));
+        }
+      }
+      Err(panic) => {
+        if let Some(reply) = reply {
+          let _ = reply.send(Err(ActorError::Panicked));
+        }
+        return Err(panic);
+      }
+    }
+  }
+
+  // The mailbox is closed, so there is nothing to restart for.
+  if let Err(panic) = catch_unwind(AssertUnwindSafe(|| actor.stopped())) {
+    report::<A>(panic);
+  }
+
+  Ok(())
+}
+
+fn report<A>(panic: Box<dyn Any + Send>) {
+  libu_log::error!(
+    "actor {} panicked: {}",
+    tynm::type_name::<A>(),
+    panic.panic_msg()
+  );
+}
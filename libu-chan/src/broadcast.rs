@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use flume::SendError;
+
+/// What a [`broadcast`] channel does when a subscriber falls more than
+/// `capacity` messages behind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+  /// Overwrite the oldest message. The lagging subscriber silently
+  /// skips what it missed; see [`Subscriber::missed`].
+  #[default]
+  DropOldest,
+  /// Block `send` until the slowest subscriber catches up.
+  Block,
+  /// Overwrite the oldest message, and make the lagging subscriber's
+  /// next receive fail with [`BroadcastRecvError::Lagged`].
+  Error,
+}
+
+/// Why a [`Subscriber`] could not receive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BroadcastRecvError {
+  /// Nothing to receive yet (`try_recv` only).
+  Empty,
+  /// Nothing arrived in time (`recv_timeout` only).
+  Timeout,
+  /// This many messages were overwritten before being received, under
+  /// [`LagPolicy::Error`]. The next receive continues with the oldest
+  /// message still buffered.
+  Lagged(u64),
+  /// Every publisher is gone and every message has been received.
+  Disconnected,
+}
+
+impl std::fmt::Display for BroadcastRecvError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Empty => write!(f, "broadcast channel is empty"),
+      Self::Timeout => write!(f, "timed out waiting on broadcast channel"),
+      Self::Lagged(n) => write!(f, "subscriber lagged behind by {n} messages"),
+      Self::Disconnected => write!(f, "broadcast channel is disconnected"),
+    }
+  }
+}
+
+impl std::error::Error for BroadcastRecvError {}
+
+/// A channel where every [`Subscriber`] receives every message sent
+/// after it subscribed.
+///
+/// Messages are buffered once and cloned out to each subscriber, and a
+/// message is dropped once every subscriber has received it. `policy`
+/// decides what happens when a subscriber is `capacity` messages behind
+/// (at least 1).
+///
+/// # Example
+///
+/// ```rust
+/// use libu_chan::{LagPolicy, broadcast};
+///
+/// let (tx, rx1) = broadcast::<&str>(16, LagPolicy::Block);
+/// let rx2 = tx.subscribe();
+///
+/// tx.send("hello").unwrap();
+/// assert_eq!(rx1.recv(), Ok("hello"));
+/// assert_eq!(rx2.recv(), Ok("hello"));
+/// ```
+pub fn broadcast<T: Clone>(capacity: usize, policy: LagPolicy) -> (Publisher<T>, Subscriber<T>) {
+  let shared = Arc::new(Shared {
+    state: Mutex::new(State {
+      buf: VecDeque::new(),
+      head: 0,
+      cursors: HashMap::new(),
+      positions: BTreeMap::new(),
+      publishers: 1,
+    }),
+    sent: Condvar::new(),
+    space: Condvar::new(),
+    capacity: capacity.max(1),
+    policy,
+    next_id: AtomicU64::new(0),
+  });
+
+  let publisher = Publisher(shared);
+  let subscriber = publisher.subscribe();
+
+  (publisher, subscriber)
+}
+
+struct Shared<T> {
+  state: Mutex<State<T>>,
+  /// Signalled on every send, and when the last publisher leaves.
+  sent: Condvar,
+  /// Signalled when the oldest buffered message is dropped, for
+  /// publishers blocked under `LagPolicy::Block`.
+  space: Condvar,
+  capacity: usize,
+  policy: LagPolicy,
+  next_id: AtomicU64,
+}
+
+struct State<T> {
+  buf: VecDeque<T>,
+  /// Sequence number of `buf[0]`.
+  head: u64,
+  /// Next sequence number each subscriber will receive.
+  cursors: HashMap<u64, u64>,
+  /// How many subscribers are at each cursor, to find the slowest one
+  /// without scanning them all.
+  positions: BTreeMap<u64, usize>,
+  publishers: usize,
+}
+
+impl<T> State<T> {
+  /// Sequence number of the next message sent.
+  fn tail(&self) -> u64 {
+    self.head + self.buf.len() as u64
+  }
+
+  /// Move subscriber `id`'s cursor to `to`, registering it if new.
+  fn seek(&mut self, id: u64, to: u64) {
+    if let Some(from) = self.cursors.insert(id, to) {
+      self.leave(from);
+    }
+    *self.positions.entry(to).or_default() += 1;
+  }
+
+  fn unsubscribe(&mut self, id: u64) {
+    if let Some(at) = self.cursors.remove(&id) {
+      self.leave(at);
+    }
+  }
+
+  fn leave(&mut self, at: u64) {
+    if let Some(count) = self.positions.get_mut(&at) {
+      *count -= 1;
+      if *count == 0 {
+        self.positions.remove(&at);
+      }
+    }
+  }
+
+  /// Drop messages every subscriber has received. Returns whether any
+  /// were dropped.
+  fn trim(&mut self) -> bool {
+    let head = self.head;
+    let min = match self.positions.first_key_value() {
+      Some((&min, _)) => min,
+      None => self.tail(),
+    };
+    while self.head < min && self.buf.pop_front().is_some() {
+      self.head += 1;
+    }
+    self.head > head
+  }
+}
+
+impl<T> Shared<T> {
+  fn lock(&self) -> MutexGuard<'_, State<T>> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  fn register(self: &Arc<Self>, state: &mut State<T>, cursor: u64) -> Subscriber<T> {
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+    state.seek(id, cursor);
+
+    Subscriber {
+      shared: self.clone(),
+      id,
+      missed: AtomicU64::new(0),
+    }
+  }
+}
+
+/// Sending half of a [`broadcast`] channel. Cloning adds a publisher;
+/// subscribers disconnect once every publisher is dropped.
+pub struct Publisher<T>(Arc<Shared<T>>);
+
+impl<T> Publisher<T> {
+  /// Send `msg` to every current subscriber. Fails, handing `msg` back,
+  /// when there are none.
+  pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+    let shared = &*self.0;
+    let mut state = shared.lock();
+
+    if shared.policy == LagPolicy::Block {
+      while !state.cursors.is_empty() && state.buf.len() >= shared.capacity {
+        state = shared.space.wait(state).unwrap_or_else(|e| e.into_inner());
+      }
+    }
+
+    if state.cursors.is_empty() {
+      return Err(SendError(msg));
+    }
+
+    if state.buf.len() >= shared.capacity {
+      state.buf.pop_front();
+      state.head += 1;
+    }
+    state.buf.push_back(msg);
+    shared.sent.notify_all();
+
+    Ok(())
+  }
+
+  /// A new subscriber that receives every message sent from now on.
+  pub fn subscribe(&self) -> Subscriber<T> {
+    let mut state = self.0.lock();
+    let tail = state.tail();
+    self.0.register(&mut state, tail)
+  }
+
+  pub fn subscriber_count(&self) -> usize {
+    self.0.lock().cursors.len()
+  }
+}
+
+impl<T> Clone for Publisher<T> {
+  fn clone(&self) -> Self {
+    self.0.lock().publishers += 1;
+    Self(self.0.clone())
+  }
+}
+
+impl<T> Drop for Publisher<T> {
+  fn drop(&mut self) {
+    let mut state = self.0.lock();
+    state.publishers -= 1;
+    if state.publishers == 0 {
+      self.0.sent.notify_all();
+    }
+  }
+}
+
+impl<T> std::fmt::Debug for Publisher<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<Publisher<T>>())
+  }
+}
+
+/// Receiving half of a [`broadcast`] channel. Cloning yields a new
+/// subscriber at the same position; dropping unsubscribes.
+pub struct Subscriber<T> {
+  shared: Arc<Shared<T>>,
+  id: u64,
+  /// Messages skipped under `LagPolicy::DropOldest`.
+  missed: AtomicU64,
+}
+
+impl<T: Clone> Subscriber<T> {
+  /// Block until the next message arrives.
+  pub fn recv(&self) -> Result<T, BroadcastRecvError> {
+    self.recv_inner(None, true)
+  }
+
+  pub fn try_recv(&self) -> Result<T, BroadcastRecvError> {
+    self.recv_inner(None, false)
+  }
+
+  pub fn recv_timeout(&self, timeout: Duration) -> Result<T, BroadcastRecvError> {
+    self.recv_inner(Some(Instant::now() + timeout), true)
+  }
+
+  /// Blocking iterator over messages, ending once the channel
+  /// disconnects. Lag errors are skipped.
+  pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+    std::iter::from_fn(|| {
+      loop {
+        match self.recv() {
+          Ok(msg) => return Some(msg),
+          Err(BroadcastRecvError::Lagged(_)) => continue,
+          Err(_) => return None,
+        }
+      }
+    })
+  }
+
+  fn recv_inner(&self, deadline: Option<Instant>, block: bool) -> Result<T, BroadcastRecvError> {
+    let shared = &*self.shared;
+    let mut state = shared.lock();
+
+    loop {
+      let head = state.head;
+      let tail = state.tail();
+      let mut cursor = state.cursors[&self.id];
+
+      if cursor < head {
+        let lagged = head - cursor;
+        cursor = head;
+        state.seek(self.id, cursor);
+        match shared.policy {
+          LagPolicy::Error => return Err(BroadcastRecvError::Lagged(lagged)),
+          _ => {
+            self.missed.fetch_add(lagged, Ordering::Relaxed);
+          }
+        }
+      }
+
+      if cursor < tail {
+        let msg = state.buf[(cursor - head) as usize].clone();
+        state.seek(self.id, cursor + 1);
+        // Only a subscriber holding the oldest message back can free it.
+        if cursor == head && state.trim() {
+          shared.space.notify_all();
+        }
+        return Ok(msg);
+      }
+
+      if state.publishers == 0 {
+        return Err(BroadcastRecvError::Disconnected);
+      }
+      if !block {
+        return Err(BroadcastRecvError::Empty);
+      }
+
+      state = match deadline {
+        None => shared.sent.wait(state).unwrap_or_else(|e| e.into_inner()),
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Err(BroadcastRecvError::Timeout);
+          }
+          shared
+            .sent
+            .wait_timeout(state, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+        }
+      };
+    }
+  }
+}
+
+impl<T> Subscriber<T> {
+  /// Messages this subscriber skipped under [`LagPolicy::DropOldest`].
+  pub fn missed(&self) -> u64 {
+    self.missed.load(Ordering::Relaxed)
+  }
+
+  /// Messages buffered for this subscriber.
+  pub fn len(&self) -> usize {
+    let state = self.shared.lock();
+    let cursor = state.cursors[&self.id].max(state.head);
+    (state.tail() - cursor) as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl<T> Clone for Subscriber<T> {
+  fn clone(&self) -> Self {
+    let mut state = self.shared.lock();
+    let cursor = state.cursors[&self.id];
+    self.shared.register(&mut state, cursor)
+  }
+}
+
+impl<T> Drop for Subscriber<T> {
+  fn drop(&mut self) {
+    let mut state = self.shared.lock();
+    state.unsubscribe(self.id);
+    // With no subscribers left, blocked sends must wake up to fail.
+    if state.trim() || state.cursors.is_empty() {
+      self.shared.space.notify_all();
+    }
+  }
+}
+
+impl<T> std::fmt::Debug for Subscriber<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<Subscriber<T>>())
+  }
+}
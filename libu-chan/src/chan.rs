@@ -1,43 +1,219 @@
-use flume::{Receiver, RecvError, SendError, Sender, TryRecvError, unbounded};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use flume::{
+  Receiver, RecvError, Selector, SendError, SendTimeoutError, Sender, TryRecvError, TrySendError,
+  bounded, unbounded,
+};
 
 /// 点对点线程安全的双向消息队列
-pub struct Chan<S, R>(Sender<S>, Receiver<R>);
+///
+/// Either end can [`close_send`](Self::close_send) to tell the peer "no
+/// more messages" while still receiving: the peer drains what is queued,
+/// then its `recv` fails and its [`iter`](Self::iter) ends.
+pub struct Chan<S, R> {
+  tx: Sender<S>,
+  rx: Receiver<R>,
+  link: Arc<Link>,
+  /// Which end of `link` this is, 0 or 1.
+  side: usize,
+}
+
+/// Close state shared by both ends of a pair.
+struct Link {
+  /// End `i` has closed its sending half.
+  closed: [AtomicBool; 2],
+  /// Never sent on; end `i` drops its sender on close, disconnecting
+  /// `wakers[i]` to wake the peer's blocked `recv`.
+  signals: [Mutex<Option<Sender<()>>>; 2],
+  wakers: [Receiver<()>; 2],
+}
+
+impl Link {
+  fn new() -> Arc<Self> {
+    let (s0, w0) = bounded(0);
+    let (s1, w1) = bounded(0);
+
+    Arc::new(Self {
+      closed: [AtomicBool::new(false), AtomicBool::new(false)],
+      signals: [Mutex::new(Some(s0)), Mutex::new(Some(s1))],
+      wakers: [w0, w1],
+    })
+  }
+}
+
+/// One end's share of a [`Link`], for the async paths.
+#[derive(Clone)]
+pub(crate) struct End {
+  link: Arc<Link>,
+  side: usize,
+}
+
+impl End {
+  /// This end's sending half is closed.
+  pub(crate) fn send_closed(&self) -> bool {
+    self.link.closed[self.side].load(Ordering::Acquire)
+  }
+
+  /// Disconnects once the peer closes its sending half.
+  pub(crate) fn peer_signal(&self) -> &Receiver<()> {
+    &self.link.wakers[1 - self.side]
+  }
+}
+
+/// How far a [`Chan`] has been closed, seen from one end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChanState {
+  Open,
+  /// This end can no longer send, but can still receive.
+  SendClosed,
+  /// The peer will send nothing more, but this end can still send.
+  RecvClosed,
+  Closed,
+}
 
 impl<S, R> Chan<S, R> {
+  /// Blocks while a bounded queue is full. Fails once this end is
+  /// [closed](Self::is_closed).
   pub fn send(&self, msg: S) -> Result<(), SendError<S>> {
-    self.0.send(msg)
+    if self.closed(self.side) {
+      return Err(SendError(msg));
+    }
+    self.tx.send(msg)
+  }
+
+  /// Fails with `TrySendError::Full` instead of blocking when the queue
+  /// is full, or when no receiver is waiting on a rendezvous channel.
+  pub fn try_send(&self, msg: S) -> Result<(), TrySendError<S>> {
+    if self.closed(self.side) {
+      return Err(TrySendError::Disconnected(msg));
+    }
+    self.tx.try_send(msg)
+  }
+
+  /// Blocks for at most `timeout` while the queue is full.
+  pub fn send_timeout(&self, msg: S, timeout: Duration) -> Result<(), SendTimeoutError<S>> {
+    self.send_deadline(msg, Instant::now() + timeout)
   }
 
+  /// Blocks until `deadline` at the latest while the queue is full.
+  pub fn send_deadline(&self, msg: S, deadline: Instant) -> Result<(), SendTimeoutError<S>> {
+    if self.closed(self.side) {
+      return Err(SendTimeoutError::Disconnected(msg));
+    }
+    self.tx.send_deadline(msg, deadline)
+  }
+
+  /// Blocks until a message arrives. Fails once the queue is empty and
+  /// the peer has closed its sending half or is gone.
   pub fn recv(&self) -> Result<R, RecvError> {
-    self.1.recv()
+    if let Ok(msg) = self.rx.try_recv() {
+      return Ok(msg);
+    }
+
+    let msg = Selector::new()
+      .recv(&self.rx, |msg| msg.ok())
+      .recv(self.close_signal(), |_| None)
+      .wait();
+
+    match msg {
+      Some(msg) => Ok(msg),
+      // Woken by a close: drain what was sent before it.
+      None => self.rx.try_recv().map_err(|_| RecvError::Disconnected),
+    }
   }
 
   pub fn try_recv(&self) -> Result<R, TryRecvError> {
-    self.1.try_recv()
+    match self.rx.try_recv() {
+      Err(TryRecvError::Empty) if self.closed(self.peer()) => Err(TryRecvError::Disconnected),
+      result => result,
+    }
   }
 
+  /// Blocking iterator over received messages. Ends once the peer has
+  /// closed its sending half or is gone, and the queue is drained.
   pub fn iter(&self) -> impl Iterator<Item = R> + '_ {
     std::iter::from_fn(|| self.recv().ok())
   }
 
+  /// Close this end's sending half: further sends fail, and the peer's
+  /// `recv` fails once it has drained what is already queued. This end
+  /// can still receive. Closing twice is a no-op.
+  ///
+  /// Only this `Chan`'s own methods observe the close; senders cloned
+  /// from [`tx`](Self::tx) keep working, as do `rx()` receivers on the
+  /// peer.
+  pub fn close_send(&self) {
+    self.link.closed[self.side].store(true, Ordering::Release);
+    lock(&self.link.signals[self.side]).take();
+  }
+
+  /// Whether sends from this end fail: it was closed with
+  /// [`close_send`](Self::close_send), or the peer is gone.
+  pub fn is_closed(&self) -> bool {
+    self.closed(self.side) || self.tx.is_disconnected()
+  }
+
+  /// Whether the peer will send nothing more: it closed its sending
+  /// half or is gone. Messages already queued can still be received.
+  pub fn peer_disconnected(&self) -> bool {
+    self.closed(self.peer()) || self.rx.is_disconnected()
+  }
+
+  pub fn state(&self) -> ChanState {
+    match (self.is_closed(), self.peer_disconnected()) {
+      (false, false) => ChanState::Open,
+      (true, false) => ChanState::SendClosed,
+      (false, true) => ChanState::RecvClosed,
+      (true, true) => ChanState::Closed,
+    }
+  }
+
   pub fn tx(&self) -> &Sender<S> {
-    &self.0
+    &self.tx
   }
 
   pub fn rx(&self) -> &Receiver<R> {
-    &self.1
+    &self.rx
+  }
+
+  /// Never receives, and disconnects once the peer closes its sending
+  /// half: wait on it next to [`rx`](Self::rx) to see the close, as
+  /// `select!` does.
+  pub fn close_signal(&self) -> &Receiver<()> {
+    &self.link.wakers[self.peer()]
+  }
+
+  pub fn into_parts(self) -> (Sender<S>, Receiver<R>) {
+    (self.tx, self.rx)
+  }
+
+  pub(crate) fn end(&self) -> End {
+    End {
+      link: self.link.clone(),
+      side: self.side,
+    }
+  }
+
+  fn peer(&self) -> usize {
+    1 - self.side
+  }
+
+  fn closed(&self, side: usize) -> bool {
+    self.link.closed[side].load(Ordering::Acquire)
   }
 }
 
 impl<S, R> AsRef<Sender<S>> for Chan<S, R> {
   fn as_ref(&self) -> &Sender<S> {
-    &self.0
+    &self.tx
   }
 }
 
 impl<S, R> AsRef<Receiver<R>> for Chan<S, R> {
   fn as_ref(&self) -> &Receiver<R> {
-    &self.1
+    &self.rx
   }
 }
 
@@ -47,9 +223,71 @@ impl<S, R> std::fmt::Debug for Chan<S, R> {
   }
 }
 
+/// Lock `mutex`, carrying on if a panicking thread poisoned it.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 pub fn channel<S, R>() -> (Chan<S, R>, Chan<R, S>) {
   let (t0, r0) = unbounded::<S>();
   let (t1, r1) = unbounded::<R>();
 
-  (Chan(t0, r1), Chan(t1, r0))
+  pair(t0, r0, t1, r1)
+}
+
+/// Like [`channel`], but each direction holds at most a fixed number of
+/// messages: `cap_ab` from the first end to the second, `cap_ba` back.
+/// `send` blocks while the peer's queue is full; a capacity of 0 makes
+/// that direction a rendezvous.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use libu_chan::channel_bounded;
+///
+/// let (a, b) = channel_bounded::<u32, ()>(1, 1);
+///
+/// a.try_send(1).unwrap();
+/// assert!(a.try_send(2).is_err());
+/// assert!(a.send_timeout(2, Duration::from_millis(10)).is_err());
+///
+/// assert_eq!(b.recv(), Ok(1));
+/// a.try_send(2).unwrap();
+/// ```
+pub fn channel_bounded<S, R>(cap_ab: usize, cap_ba: usize) -> (Chan<S, R>, Chan<R, S>) {
+  let (t0, r0) = bounded::<S>(cap_ab);
+  let (t1, r1) = bounded::<R>(cap_ba);
+
+  pair(t0, r0, t1, r1)
+}
+
+/// A [`channel_bounded`] with no buffering in either direction: every
+/// `send` waits until the peer receives the message.
+pub fn channel_rendezvous<S, R>() -> (Chan<S, R>, Chan<R, S>) {
+  channel_bounded(0, 0)
+}
+
+fn pair<S, R>(
+  t0: Sender<S>,
+  r0: Receiver<S>,
+  t1: Sender<R>,
+  r1: Receiver<R>,
+) -> (Chan<S, R>, Chan<R, S>) {
+  let link = Link::new();
+
+  let a = Chan {
+    tx: t0,
+    rx: r1,
+    link: link.clone(),
+    side: 0,
+  };
+  let b = Chan {
+    tx: t1,
+    rx: r0,
+    link,
+    side: 1,
+  };
+
+  (a, b)
 }
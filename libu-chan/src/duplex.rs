@@ -0,0 +1,68 @@
+use flume::{RecvError, SendError};
+
+use crate::chan::*;
+
+/// One end of a two-way channel, whether in-process ([`Chan`]) or
+/// across processes ([`IpcChan`](crate::IpcChan)), so code can be
+/// written once against either.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_chan::{Duplex, channel};
+///
+/// fn echo<D: Duplex<u32, u32>>(end: &D) {
+///   for n in end.iter() {
+///     if end.send(n).is_err() {
+///       break;
+///     }
+///   }
+/// }
+///
+/// let (a, b) = channel::<u32, u32>();
+/// a.send(7).unwrap();
+/// a.close_send();
+/// echo(&b);
+/// assert_eq!(a.recv(), Ok(7));
+/// ```
+pub trait Duplex<S, R> {
+  type SendError: std::error::Error;
+  type RecvError: std::error::Error;
+
+  /// Send `msg` to the peer, blocking while the transport is full.
+  fn send(&self, msg: S) -> Result<(), Self::SendError>;
+
+  /// Block until a message arrives; fails once the peer has closed its
+  /// sending half or is gone and everything sent has been received.
+  fn recv(&self) -> Result<R, Self::RecvError>;
+
+  /// Tell the peer no more messages will be sent. This end can still
+  /// receive.
+  fn close_send(&self);
+
+  /// Blocking iterator over received messages, ending when `recv`
+  /// fails.
+  fn iter(&self) -> impl Iterator<Item = R> + '_
+  where
+    Self: Sized,
+  {
+    std::iter::from_fn(|| self.recv().ok())
+  }
+}
+
+impl<S, R> Duplex<S, R> for Chan<S, R> {
+  type SendError = SendError<S>;
+  type RecvError = RecvError;
+
+  fn send(&self, msg: S) -> Result<(), SendError<S>> {
+    Chan::send(self, msg)
+  }
+
+  fn recv(&self) -> Result<R, RecvError> {
+    Chan::recv(self)
+  }
+
+  fn close_send(&self) {
+    Chan::close_send(self)
+  }
+}
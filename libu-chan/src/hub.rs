@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use flume::SendError;
+
+use crate::broadcast::*;
+
+/// Topic-based publish/subscribe: a [`broadcast`] channel per topic,
+/// created on first subscribe.
+///
+/// Topics are any hashable key, typically a `&'static str` or an enum.
+/// Subscribers come and go at runtime: [`subscribe`](Self::subscribe)
+/// registers one, and dropping the [`Subscriber`] unregisters it. The
+/// hub is cheap to clone and every clone shares the same topics.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_chan::{Hub, LagPolicy};
+///
+/// #[derive(Clone, PartialEq, Eq, Hash)]
+/// enum Topic { Orders, Prices }
+///
+/// let hub = Hub::<Topic, u32>::new(64, LagPolicy::DropOldest);
+/// let orders = hub.subscribe(Topic::Orders);
+///
+/// hub.publish(&Topic::Orders, 1).unwrap();
+/// assert!(hub.publish(&Topic::Prices, 2).is_err());
+/// assert_eq!(orders.recv(), Ok(1));
+///
+/// drop(orders);
+/// assert!(hub.publish(&Topic::Orders, 3).is_err());
+/// ```
+pub struct Hub<K, T>(Arc<HubInner<K, T>>);
+
+struct HubInner<K, T> {
+  topics: Mutex<HashMap<K, Publisher<T>>>,
+  capacity: usize,
+  policy: LagPolicy,
+}
+
+impl<K, T> Hub<K, T>
+where
+  K: Hash + Eq + Clone,
+  T: Clone,
+{
+  /// A hub whose topics buffer `capacity` messages under `policy`.
+  pub fn new(capacity: usize, policy: LagPolicy) -> Self {
+    Self(Arc::new(HubInner {
+      topics: Mutex::new(HashMap::new()),
+      capacity,
+      policy,
+    }))
+  }
+
+  /// Receive every message published on `topic` from now on.
+  pub fn subscribe(&self, topic: K) -> Subscriber<T> {
+    let inner = &*self.0;
+    let mut topics = inner.lock();
+
+    match topics.get(&topic) {
+      Some(publisher) => publisher.subscribe(),
+      None => {
+        let (publisher, subscriber) = broadcast(inner.capacity, inner.policy);
+        topics.insert(topic, publisher);
+        subscriber
+      }
+    }
+  }
+
+  /// Send `msg` to every subscriber of `topic`. Fails, handing `msg`
+  /// back, when there are none.
+  ///
+  /// Under [`LagPolicy::Block`] this waits for the topic's slowest
+  /// subscriber, without holding up other topics.
+  pub fn publish(&self, topic: &K, msg: T) -> Result<(), SendError<T>> {
+    let Some(publisher) = self.0.lock().get(topic).cloned() else {
+      return Err(SendError(msg));
+    };
+
+    let result = publisher.send(msg);
+    if result.is_err() {
+      // Every subscriber is gone; forget the topic unless someone
+      // subscribed again meanwhile.
+      let mut topics = self.0.lock();
+      if topics.get(topic).is_some_and(|p| p.subscriber_count() == 0) {
+        topics.remove(topic);
+      }
+    }
+
+    result
+  }
+
+  /// Topics with at least one subscriber.
+  pub fn topics(&self) -> Vec<K> {
+    self
+      .0
+      .lock()
+      .iter()
+      .filter(|(_, publisher)| publisher.subscriber_count() > 0)
+      .map(|(topic, _)| topic.clone())
+      .collect()
+  }
+
+  pub fn subscriber_count(&self, topic: &K) -> usize {
+    self
+      .0
+      .lock()
+      .get(topic)
+      .map_or(0, |publisher| publisher.subscriber_count())
+  }
+}
+
+impl<K, T> HubInner<K, T> {
+  fn lock(&self) -> MutexGuard<'_, HashMap<K, Publisher<T>>> {
+    self.topics.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl<K, T> Clone for Hub<K, T> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<K, T> std::fmt::Debug for Hub<K, T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<Hub<K, T>>())
+  }
+}
@@ -0,0 +1,401 @@
+//! A [`Chan`](crate::Chan)-like channel between processes, over a Unix
+//! domain socket.
+
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::chan::lock;
+use crate::duplex::*;
+
+/// Largest frame [`IpcChan`] sends or accepts, in bytes. Larger
+/// messages fail with [`IpcError::FrameTooLarge`].
+pub const MAX_FRAME_LEN: usize = 64 << 20;
+
+/// Turns messages into bytes and back, for [`IpcChan`].
+///
+/// A codec is usually a stateless unit struct implementing this for
+/// every message type it supports. With the `serde` feature,
+/// [`BincodeCodec`] handles any serde type.
+pub trait Codec<T> {
+  type Error: std::error::Error + Send + Sync + 'static;
+
+  fn encode(&self, msg: &T) -> Result<Vec<u8>, Self::Error>;
+
+  fn decode(&self, bytes: &[u8]) -> Result<T, Self::Error>;
+}
+
+/// Encodes serde types with bincode's standard configuration.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "serde")]
+impl<T> Codec<T> for BincodeCodec
+where
+  T: serde::Serialize + serde::de::DeserializeOwned,
+{
+  type Error = BincodeError;
+
+  fn encode(&self, msg: &T) -> Result<Vec<u8>, BincodeError> {
+    bincode::serde::encode_to_vec(msg, bincode::config::standard()).map_err(BincodeError::Encode)
+  }
+
+  fn decode(&self, bytes: &[u8]) -> Result<T, BincodeError> {
+    bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+      .map(|(msg, _)| msg)
+      .map_err(BincodeError::Decode)
+  }
+}
+
+/// Why [`BincodeCodec`] failed.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum BincodeError {
+  Encode(bincode::error::EncodeError),
+  Decode(bincode::error::DecodeError),
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for BincodeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Encode(e) => write!(f, "bincode encode failed: {e}"),
+      Self::Decode(e) => write!(f, "bincode decode failed: {e}"),
+    }
+  }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for BincodeError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Encode(e) => Some(e),
+      Self::Decode(e) => Some(e),
+    }
+  }
+}
+
+/// Why an [`IpcChan`] operation failed.
+#[derive(Debug)]
+pub enum IpcError {
+  /// The peer closed its end, or this end's sending half is closed.
+  Disconnected,
+  /// A frame exceeded [`MAX_FRAME_LEN`]; holds its length. Not fatal:
+  /// a received frame is skipped, a sent one is never written.
+  FrameTooLarge(usize),
+  /// The codec rejected a message.
+  Codec(Box<dyn std::error::Error + Send + Sync>),
+  Io(io::Error),
+}
+
+impl std::fmt::Display for IpcError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Disconnected => write!(f, "ipc channel disconnected"),
+      Self::FrameTooLarge(len) => write!(f, "ipc frame of {len} bytes exceeds {MAX_FRAME_LEN}"),
+      Self::Codec(e) => write!(f, "ipc codec error: {e}"),
+      Self::Io(e) => write!(f, "ipc io error: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for IpcError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Codec(e) => Some(&**e),
+      Self::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for IpcError {
+  fn from(e: io::Error) -> Self {
+    match e.kind() {
+      ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::UnexpectedEof => {
+        Self::Disconnected
+      }
+      _ => Self::Io(e),
+    }
+  }
+}
+
+/// One end of a two-way channel to another process: sends `S`, receives
+/// `R`, each framed as a little-endian `u32` length and the bytes `C`
+/// encodes it to.
+///
+/// The API mirrors [`Chan`](crate::Chan) and both implement [`Duplex`].
+/// Sends and receives may happen from different threads at once;
+/// concurrent sends are written whole, one after another.
+///
+/// Create a connected pair with [`ipc_pair`] before forking, or connect
+/// to an [`IpcListener`] by path.
+///
+/// # Example
+///
+/// ```rust
+/// use std::thread;
+/// use libu_chan::{Codec, IpcChan, ipc_pair};
+///
+/// #[derive(Default)]
+/// struct Le;
+///
+/// impl Codec<u32> for Le {
+///   type Error = std::array::TryFromSliceError;
+///
+///   fn encode(&self, msg: &u32) -> Result<Vec<u8>, Self::Error> {
+///     Ok(msg.to_le_bytes().to_vec())
+///   }
+///
+///   fn decode(&self, bytes: &[u8]) -> Result<u32, Self::Error> {
+///     Ok(u32::from_le_bytes(bytes.try_into()?))
+///   }
+/// }
+///
+/// let (a, b) = ipc_pair::<u32, u32, Le>().unwrap();
+/// thread::spawn(move || {
+///   for n in b.iter() {
+///     b.send(n * 2).unwrap();
+///   }
+/// });
+///
+/// a.send(21).unwrap();
+/// assert_eq!(a.recv().unwrap(), 42);
+/// ```
+pub struct IpcChan<S, R, C> {
+  reader: Mutex<BufReader<UnixStream>>,
+  writer: Mutex<BufWriter<UnixStream>>,
+  codec: C,
+  _msgs: PhantomData<fn(S) -> R>,
+}
+
+impl<S, R, C> IpcChan<S, R, C>
+where
+  C: Codec<S> + Codec<R>,
+{
+  /// Wrap a connected stream.
+  pub fn from_stream(stream: UnixStream, codec: C) -> io::Result<Self> {
+    Ok(Self {
+      reader: Mutex::new(BufReader::new(stream.try_clone()?)),
+      writer: Mutex::new(BufWriter::new(stream)),
+      codec,
+      _msgs: PhantomData,
+    })
+  }
+
+  /// Connect to the [`IpcListener`] bound at `path`.
+  pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self>
+  where
+    C: Default,
+  {
+    Self::from_stream(UnixStream::connect(path)?, C::default())
+  }
+
+  /// Send `msg`, blocking while the socket buffer is full.
+  pub fn send(&self, msg: S) -> Result<(), IpcError> {
+    let bytes = Codec::<S>::encode(&self.codec, &msg).map_err(|e| IpcError::Codec(e.into()))?;
+    if bytes.len() > MAX_FRAME_LEN {
+      return Err(IpcError::FrameTooLarge(bytes.len()));
+    }
+
+    let mut writer = lock(&self.writer);
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+
+    Ok(())
+  }
+
+  /// Block until a message arrives. Fails with
+  /// [`IpcError::Disconnected`] once the peer has closed its sending
+  /// half or exited.
+  ///
+  /// A frame over [`MAX_FRAME_LEN`] is read and discarded, then reported
+  /// as [`IpcError::FrameTooLarge`]; the channel stays usable and the
+  /// next call receives the following message.
+  pub fn recv(&self) -> Result<R, IpcError> {
+    let mut reader = lock(&self.reader);
+
+    let mut len = [0; 4];
+    if !read_frame_start(&mut *reader, &mut len)? {
+      return Err(IpcError::Disconnected);
+    }
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+      // Skip the body so the next `recv` starts at a frame boundary.
+      let skipped = io::copy(&mut (&mut *reader).take(len as u64), &mut io::sink())?;
+      if skipped < len as u64 {
+        return Err(IpcError::Disconnected);
+      }
+      return Err(IpcError::FrameTooLarge(len));
+    }
+
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    drop(reader);
+
+    Codec::<R>::decode(&self.codec, &bytes).map_err(|e| IpcError::Codec(e.into()))
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = R> + '_ {
+    std::iter::from_fn(|| self.recv().ok())
+  }
+
+  /// Shut down the socket's write half: the peer's `recv` fails once it
+  /// has read everything already sent. This end can still receive.
+  pub fn close_send(&self) {
+    let writer = lock(&self.writer);
+    let _ = writer.get_ref().shutdown(Shutdown::Write);
+  }
+}
+
+impl<S, R, C> Duplex<S, R> for IpcChan<S, R, C>
+where
+  C: Codec<S> + Codec<R>,
+{
+  type SendError = IpcError;
+  type RecvError = IpcError;
+
+  fn send(&self, msg: S) -> Result<(), IpcError> {
+    IpcChan::send(self, msg)
+  }
+
+  fn recv(&self) -> Result<R, IpcError> {
+    IpcChan::recv(self)
+  }
+
+  fn close_send(&self) {
+    IpcChan::close_send(self)
+  }
+}
+
+impl<S, R, C> std::fmt::Debug for IpcChan<S, R, C> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<IpcChan<S, R, C>>())
+  }
+}
+
+/// Two connected [`IpcChan`] ends.
+pub type IpcPair<S, R, C> = (IpcChan<S, R, C>, IpcChan<R, S, C>);
+
+/// A connected pair of [`IpcChan`]s over a socketpair. Fork, or pass
+/// one end's descriptor to a child, to use it across processes.
+pub fn ipc_pair<S, R, C>() -> io::Result<IpcPair<S, R, C>>
+where
+  C: Codec<S> + Codec<R> + Default,
+{
+  let (a, b) = UnixStream::pair()?;
+
+  Ok((
+    IpcChan::from_stream(a, C::default())?,
+    IpcChan::from_stream(b, C::default())?,
+  ))
+}
+
+/// Accepts [`IpcChan`] connections on a socket path.
+pub struct IpcListener<S, R, C> {
+  listener: UnixListener,
+  _chan: PhantomData<IpcChan<S, R, C>>,
+}
+
+impl<S, R, C> IpcListener<S, R, C>
+where
+  C: Codec<S> + Codec<R> + Default,
+{
+  pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    Ok(Self {
+      listener: UnixListener::bind(path)?,
+      _chan: PhantomData,
+    })
+  }
+
+  /// Block until a peer connects.
+  pub fn accept(&self) -> io::Result<IpcChan<S, R, C>> {
+    let (stream, _) = self.listener.accept()?;
+    IpcChan::from_stream(stream, C::default())
+  }
+}
+
+impl<S, R, C> std::fmt::Debug for IpcListener<S, R, C> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<IpcListener<S, R, C>>())
+  }
+}
+
+/// Read a frame's length prefix. Returns `false` on a clean end of
+/// stream before the first byte.
+fn read_frame_start(reader: &mut impl Read, buf: &mut [u8; 4]) -> io::Result<bool> {
+  let mut read = 0;
+  while read < buf.len() {
+    match reader.read(&mut buf[read..]) {
+      Ok(0) if read == 0 => return Ok(false),
+      Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+      Ok(n) => read += n,
+      Err(e) if e.kind() == ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::thread;
+
+  use super::*;
+
+  #[derive(Default)]
+  struct Le;
+
+  impl Codec<u32> for Le {
+    type Error = std::array::TryFromSliceError;
+
+    fn encode(&self, msg: &u32) -> Result<Vec<u8>, Self::Error> {
+      Ok(msg.to_le_bytes().to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<u32, Self::Error> {
+      Ok(u32::from_le_bytes(bytes.try_into()?))
+    }
+  }
+
+  #[test]
+  fn oversized_frame_is_skipped() {
+    let (a, mut raw) = UnixStream::pair().unwrap();
+    let a = IpcChan::<u32, u32, Le>::from_stream(a, Le).unwrap();
+
+    // Write the frames by hand: `send` refuses to produce an oversized one.
+    let writer = thread::spawn(move || {
+      let len = MAX_FRAME_LEN + 1;
+      raw.write_all(&(len as u32).to_le_bytes()).unwrap();
+      io::copy(&mut io::repeat(0).take(len as u64), &mut raw).unwrap();
+      raw.write_all(&4u32.to_le_bytes()).unwrap();
+      raw.write_all(&7u32.to_le_bytes()).unwrap();
+    });
+
+    assert!(matches!(a.recv(), Err(IpcError::FrameTooLarge(len)) if len == MAX_FRAME_LEN + 1));
+    assert_eq!(a.recv().unwrap(), 7);
+    writer.join().unwrap();
+  }
+
+  #[test]
+  fn close_send_ends_the_peer_iter() {
+    let (a, b) = ipc_pair::<u32, u32, Le>().unwrap();
+    a.send(1).unwrap();
+    a.send(2).unwrap();
+    a.close_send();
+
+    assert_eq!(b.iter().collect::<Vec<_>>(), [1, 2]);
+    assert!(matches!(b.recv(), Err(IpcError::Disconnected)));
+
+    // Only one direction is closed.
+    b.send(3).unwrap();
+    assert_eq!(a.recv().unwrap(), 3);
+  }
+}
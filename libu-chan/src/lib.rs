@@ -1,6 +1,29 @@
 #![allow(unused)]
 #![allow(non_snake_case)]
 
+mod actor;
+mod broadcast;
 mod chan;
+mod duplex;
+mod hub;
+#[cfg(unix)]
+mod ipc;
+mod rpc;
+#[cfg(feature = "async")]
+mod stream;
+mod watch;
 
+pub use actor::*;
+pub use broadcast::*;
 pub use chan::*;
+pub use duplex::*;
+pub use flume::{
+  RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError,
+};
+pub use hub::*;
+#[cfg(unix)]
+pub use ipc::*;
+pub use rpc::*;
+#[cfg(feature = "async")]
+pub use stream::*;
+pub use watch::*;
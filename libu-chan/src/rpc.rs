@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flume::{RecvError, RecvTimeoutError, Sender, bounded};
+
+use crate::chan::*;
+
+/// Request/reply over a [`Chan`]: every request carries an ID and the
+/// reply is routed back to the caller that sent it.
+///
+/// The client is cheap to clone and every clone shares one connection,
+/// so any number of threads can have calls in flight at once. Replies
+/// are routed by a `libu-chan-rpc` thread, which exits once the server
+/// is dropped.
+///
+/// # Example
+///
+/// ```rust
+/// use std::thread;
+/// use std::time::Duration;
+/// use libu_chan::rpc;
+///
+/// let (client, server) = rpc::<u32, u32>();
+/// thread::spawn(move || server.serve(|n| n * 2));
+///
+/// let workers = (0..4)
+///   .map(|i| {
+///     let client = client.clone();
+///     thread::spawn(move || client.call_timeout(i, Duration::from_secs(1)))
+///   })
+///   .collect::<Vec<_>>();
+///
+/// for (i, worker) in workers.into_iter().enumerate() {
+///   assert_eq!(worker.join().unwrap(), Ok(i as u32 * 2));
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if the routing thread cannot be spawned.
+pub fn rpc<Req, Resp>() -> (RpcClient<Req, Resp>, RpcServer<Req, Resp>)
+where
+  Resp: Send + 'static,
+{
+  let (client, server) = channel::<(u64, Req), RpcWire<Resp>>();
+  let pending: RpcPending<Resp> = Arc::new(Mutex::new(Some(HashMap::new())));
+
+  let replies = client.rx().clone();
+  let routes = pending.clone();
+  thread::Builder::new()
+    .name("libu-chan-rpc".to_owned())
+    .spawn(move || {
+      for (id, resp) in replies.iter() {
+        // No entry: the caller timed out already.
+        if let Some(tx) = lock(&routes).as_mut().and_then(|map| map.remove(&id)) {
+          let _ = tx.send(resp);
+        }
+      }
+
+      // Dropping every reply sender fails the calls in flight.
+      lock(&routes).take();
+    })
+    .expect("failed to spawn rpc routing thread");
+
+  let client = RpcClient(Arc::new(RpcClientInner {
+    chan: client,
+    next_id: AtomicU64::new(0),
+    pending,
+  }));
+
+  (client, RpcServer(server))
+}
+
+/// Why an RPC call failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcError {
+  /// The server is gone.
+  Disconnected,
+  /// The server dropped the request without replying.
+  NoReply,
+  /// No reply arrived in time. A late reply is discarded.
+  Timeout,
+}
+
+impl std::fmt::Display for RpcError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Disconnected => write!(f, "rpc server disconnected"),
+      Self::NoReply => write!(f, "rpc request dropped without a reply"),
+      Self::Timeout => write!(f, "rpc call timed out"),
+    }
+  }
+}
+
+impl std::error::Error for RpcError {}
+
+/// A reply on the wire, `None` for a request dropped unanswered.
+type RpcWire<Resp> = (u64, Option<Resp>);
+
+/// Where to deliver the reply of each call in flight. `None` once the
+/// routing thread has exited.
+type RpcPending<Resp> = Arc<Mutex<Option<HashMap<u64, Sender<Option<Resp>>>>>>;
+
+/// Calling end of an [`rpc`] pair.
+pub struct RpcClient<Req, Resp>(Arc<RpcClientInner<Req, Resp>>);
+
+struct RpcClientInner<Req, Resp> {
+  chan: Chan<(u64, Req), RpcWire<Resp>>,
+  next_id: AtomicU64,
+  pending: RpcPending<Resp>,
+}
+
+impl<Req, Resp> Clone for RpcClient<Req, Resp> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<Req, Resp> RpcClient<Req, Resp> {
+  /// Send `req` and block until its reply arrives.
+  pub fn call(&self, req: Req) -> Result<Resp, RpcError> {
+    self.call_inner(req, None)
+  }
+
+  /// Like [`call`](Self::call), giving up after `timeout`.
+  pub fn call_timeout(&self, req: Req, timeout: Duration) -> Result<Resp, RpcError> {
+    self.call_inner(req, Some(Instant::now() + timeout))
+  }
+
+  /// Like [`call`](Self::call), giving up at `deadline`.
+  pub fn call_deadline(&self, req: Req, deadline: Instant) -> Result<Resp, RpcError> {
+    self.call_inner(req, Some(deadline))
+  }
+
+  fn call_inner(&self, req: Req, deadline: Option<Instant>) -> Result<Resp, RpcError> {
+    let inner = &*self.0;
+    let id = inner.next_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = bounded(1);
+
+    match lock(&inner.pending).as_mut() {
+      Some(map) => map.insert(id, tx),
+      None => return Err(RpcError::Disconnected),
+    };
+
+    if inner.chan.send((id, req)).is_err() {
+      self.forget(id);
+      return Err(RpcError::Disconnected);
+    }
+
+    let result = match deadline {
+      Some(deadline) => rx.recv_deadline(deadline).map_err(|e| match e {
+        RecvTimeoutError::Timeout => RpcError::Timeout,
+        RecvTimeoutError::Disconnected => RpcError::Disconnected,
+      }),
+      None => rx.recv().map_err(|_| RpcError::Disconnected),
+    };
+
+    let result = match result {
+      // Already routed: the reply is on its way, so take it rather
+      // than lose it.
+      Err(RpcError::Timeout) if !self.forget(id) => rx.recv().map_err(|_| RpcError::Timeout),
+      result => result,
+    };
+    result?.ok_or(RpcError::NoReply)
+  }
+
+  /// Drop the route for `id`. Returns `false` if the router already
+  /// took it.
+  fn forget(&self, id: u64) -> bool {
+    lock(&self.0.pending)
+      .as_mut()
+      .is_some_and(|map| map.remove(&id).is_some())
+  }
+}
+
+impl<Req, Resp> std::fmt::Debug for RpcClient<Req, Resp> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<RpcClient<Req, Resp>>())
+  }
+}
+
+/// Serving end of an [`rpc`] pair. Share it behind an `Arc` to serve
+/// from several threads; each request goes to exactly one of them.
+pub struct RpcServer<Req, Resp>(Chan<RpcWire<Resp>, (u64, Req)>);
+
+impl<Req, Resp> RpcServer<Req, Resp> {
+  /// Answer every request with `handler` until all clients are
+  /// dropped.
+  pub fn serve<F>(&self, mut handler: F)
+  where
+    F: FnMut(Req) -> Resp,
+  {
+    while let Ok(RpcRequest { body, reply }) = self.recv() {
+      let _ = reply.send(handler(body));
+    }
+  }
+
+  /// Take the next request, to answer it later or from another thread.
+  pub fn recv(&self) -> Result<RpcRequest<Req, Resp>, RecvError> {
+    let (id, body) = self.0.recv()?;
+
+    Ok(RpcRequest {
+      body,
+      reply: RpcReply {
+        id,
+        tx: Some(self.0.tx().clone()),
+      },
+    })
+  }
+}
+
+impl<Req, Resp> std::fmt::Debug for RpcServer<Req, Resp> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<RpcServer<Req, Resp>>())
+  }
+}
+
+/// A request taken with [`RpcServer::recv`]. Dropping it without
+/// replying fails the call with [`RpcError::NoReply`].
+pub struct RpcRequest<Req, Resp> {
+  pub body: Req,
+  reply: RpcReply<Resp>,
+}
+
+impl<Req, Resp> RpcRequest<Req, Resp> {
+  /// Send the reply. Fails if every client is gone.
+  pub fn reply(self, resp: Resp) -> Result<(), RpcError> {
+    self.reply.send(resp)
+  }
+}
+
+/// The way back to the caller of one request. Sends the "no reply"
+/// marker if dropped unused, so the router drops the call's route.
+struct RpcReply<Resp> {
+  id: u64,
+  /// `None` once the reply is sent.
+  tx: Option<Sender<RpcWire<Resp>>>,
+}
+
+impl<Resp> RpcReply<Resp> {
+  fn send(mut self, resp: Resp) -> Result<(), RpcError> {
+    let tx = self.tx.take().expect("reply sent once");
+    tx.send((self.id, Some(resp)))
+      .map_err(|_| RpcError::Disconnected)
+  }
+}
+
+impl<Resp> Drop for RpcReply<Resp> {
+  fn drop(&mut self) {
+    if let Some(tx) = self.tx.take() {
+      let _ = tx.send((self.id, None));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::thread;
+
+  use super::*;
+
+  #[test]
+  fn dropped_request_fails_the_call() {
+    let (client, server) = rpc::<u32, u32>();
+    let server = thread::spawn(move || {
+      // Drop the first request unanswered, answer the second.
+      drop(server.recv().unwrap());
+      let request = server.recv().unwrap();
+      let n = request.body;
+      request.reply(n + 1).unwrap();
+    });
+
+    assert_eq!(client.call(1), Err(RpcError::NoReply));
+    assert_eq!(client.call(2), Ok(3));
+    server.join().unwrap();
+    assert_eq!(client.call(3), Err(RpcError::Disconnected));
+  }
+}
@@ -0,0 +1,201 @@
+//! Async access to a [`Chan`], enabled by the `async` feature.
+//!
+//! Both ends of a channel pair can mix blocking and async use freely,
+//! so an async task can talk to a plain thread over the same pair.
+
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use flume::r#async::{RecvStream, SendSink};
+use flume::{Receiver, RecvError, SendError};
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::chan::*;
+
+pub use flume::r#async::{RecvFut, SendFut};
+
+impl<S, R> Chan<S, R> {
+  /// Receive without blocking the thread. Fails once the queue is empty
+  /// and the peer has closed its sending half or is gone.
+  pub async fn recv_async(&self) -> Result<R, RecvError> {
+    let end = self.end();
+    let mut recv = self.rx().recv_async();
+    let mut closed = end.peer_signal().recv_async();
+
+    poll_fn(|cx| {
+      if let Poll::Ready(result) = Pin::new(&mut recv).poll(cx) {
+        return Poll::Ready(result);
+      }
+
+      match Pin::new(&mut closed).poll(cx) {
+        // Woken by a close: drain what was sent before it.
+        Poll::Ready(_) => Poll::Ready(self.rx().try_recv().map_err(|_| RecvError::Disconnected)),
+        Poll::Pending => Poll::Pending,
+      }
+    })
+    .await
+  }
+
+  /// Send without blocking the thread; waits for room on a bounded
+  /// channel. Fails once this end is [closed](Self::is_closed).
+  pub async fn send_async(&self, msg: S) -> Result<(), SendError<S>> {
+    if self.end().send_closed() {
+      return Err(SendError(msg));
+    }
+    self.tx().send_async(msg).await
+  }
+
+  /// Borrow the channel as a [`ChanStream`].
+  pub fn stream(&self) -> ChanStream<'_, S, R> {
+    let end = self.end();
+
+    ChanStream {
+      tx: self.tx().sink(),
+      rx: self.rx().stream(),
+      queue: self.rx().clone(),
+      closed: end.peer_signal().clone().into_stream(),
+      end,
+    }
+  }
+
+  /// Turn the channel into a [`ChanStream`] that owns it.
+  pub fn into_stream(self) -> ChanStream<'static, S, R> {
+    let end = self.end();
+    let (tx, rx) = self.into_parts();
+
+    ChanStream {
+      tx: tx.into_sink(),
+      queue: rx.clone(),
+      rx: rx.into_stream(),
+      closed: end.peer_signal().clone().into_stream(),
+      end,
+    }
+  }
+}
+
+/// A [`Chan`] seen as a `Stream` of received messages and a `Sink` for
+/// sent ones.
+///
+/// Half-close works as on the `Chan`: the stream ends once the peer has
+/// closed its sending half and the queue is drained, and sending fails
+/// once this end was closed with [`Chan::close_send`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use futures::{SinkExt, StreamExt};
+/// use libu_chan::channel;
+///
+/// let (a, b) = channel::<u32, u32>();
+///
+/// // A plain thread doubles every number.
+/// std::thread::spawn(move || b.iter().for_each(|n| b.send(n * 2).unwrap()));
+///
+/// let mut a = a.into_stream();
+/// a.send(21).await?;
+/// assert_eq!(a.next().await, Some(42));
+/// ```
+pub struct ChanStream<'a, S, R> {
+  tx: SendSink<'a, S>,
+  rx: RecvStream<'a, R>,
+  /// Drained once `closed` ends.
+  queue: Receiver<R>,
+  /// Ends when the peer closes its sending half.
+  closed: RecvStream<'static, ()>,
+  end: End,
+}
+
+impl<S, R> Stream for ChanStream<'_, S, R> {
+  type Item = R;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<R>> {
+    if let Poll::Ready(msg) = Pin::new(&mut self.rx).poll_next(cx) {
+      return Poll::Ready(msg);
+    }
+
+    match Pin::new(&mut self.closed).poll_next(cx) {
+      Poll::Ready(_) => Poll::Ready(self.queue.try_recv().ok()),
+      Poll::Pending => Poll::Pending,
+    }
+  }
+}
+
+impl<S, R> Sink<S> for ChanStream<'_, S, R> {
+  type Error = SendError<S>;
+
+  fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Pin::new(&mut self.tx).poll_ready(cx)
+  }
+
+  fn start_send(mut self: Pin<&mut Self>, msg: S) -> Result<(), Self::Error> {
+    if self.end.send_closed() {
+      return Err(SendError(msg));
+    }
+    Pin::new(&mut self.tx).start_send(msg)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Pin::new(&mut self.tx).poll_flush(cx)
+  }
+
+  fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Pin::new(&mut self.tx).poll_close(cx)
+  }
+}
+
+impl<S, R> std::fmt::Debug for ChanStream<'_, S, R> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<ChanStream<S, R>>())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::task::Waker;
+
+  use super::*;
+  use crate::channel;
+
+  /// Poll `fut` to completion on the current thread.
+  fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = std::pin::pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+      if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+        return out;
+      }
+      std::thread::yield_now();
+    }
+  }
+
+  #[test]
+  fn recv_async_drains_then_ends_after_close_send() {
+    let (a, b) = channel::<u32, u32>();
+    a.send(1).unwrap();
+    a.send(2).unwrap();
+    a.close_send();
+
+    assert_eq!(block_on(b.recv_async()), Ok(1));
+    assert_eq!(block_on(b.recv_async()), Ok(2));
+    assert_eq!(block_on(b.recv_async()), Err(RecvError::Disconnected));
+    assert!(block_on(a.send_async(3)).is_err());
+  }
+
+  #[test]
+  fn stream_drains_then_ends_after_close_send() {
+    let (a, b) = channel::<u32, u32>();
+    let mut stream = b.stream();
+    a.send(1).unwrap();
+    a.send(2).unwrap();
+    a.close_send();
+
+    let mut next = || block_on(poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)));
+    assert_eq!(next(), Some(1));
+    assert_eq!(next(), Some(2));
+    assert_eq!(next(), None);
+    // `a` is still alive; only the close ended the stream.
+    drop(a);
+  }
+}
@@ -0,0 +1,325 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use flume::{Receiver, RecvError, RecvTimeoutError, Sender, TrySendError, bounded};
+
+/// A channel holding a single "current value", for state such as
+/// configuration that many threads read but only care about the latest
+/// version of.
+///
+/// Every [`send`](WatchSender::send) replaces the value and bumps its
+/// version. Receivers [`borrow`](WatchReceiver::borrow) the value at any
+/// time, or block in [`changed`](WatchReceiver::changed) until a version
+/// they have not seen yet is published. Intermediate versions are
+/// skipped, never queued.
+///
+/// # Selecting
+///
+/// [`WatchReceiver::rx`] is a flume receiver that becomes ready when a
+/// new version is published and disconnects once every sender is gone,
+/// so a watch can be waited on with `select!` alongside regular `Chan`s.
+/// The handler gets the new version; read the value with
+/// [`borrow_and_update`](WatchReceiver::borrow_and_update).
+///
+/// `select!` adds each arm to a flume `Selector`; by hand, that is:
+///
+/// ```rust
+/// use flume::Selector;
+/// use libu_chan::{channel, watch};
+///
+/// let (config_tx, config) = watch(1);
+/// let (_a, b) = channel::<&str, ()>();
+/// config_tx.send(2);
+///
+/// let event = Selector::new()
+///   .recv(config.rx(), |_| format!("config {}", *config.borrow_and_update()))
+///   .recv(b.rx(), |msg| format!("message {}", msg.unwrap()))
+///   .wait();
+/// assert_eq!(event, "config 2");
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// use std::thread;
+/// use libu_chan::watch;
+///
+/// let (tx, rx) = watch("v1");
+/// assert_eq!(*rx.borrow(), "v1");
+///
+/// let reader = thread::spawn(move || {
+///   let version = rx.changed().unwrap();
+///   (version, *rx.borrow())
+/// });
+///
+/// tx.send("v2");
+/// assert_eq!(reader.join().unwrap(), (1, "v2"));
+/// ```
+pub fn watch<T>(init: T) -> (WatchSender<T>, WatchReceiver<T>) {
+  let shared = Arc::new(WatchShared {
+    state: RwLock::new(WatchState {
+      value: init,
+      version: 0,
+      watchers: Vec::new(),
+      senders: 1,
+    }),
+  });
+
+  let sender = WatchSender(shared);
+  let receiver = sender.subscribe();
+
+  (sender, receiver)
+}
+
+struct WatchShared<T> {
+  state: RwLock<WatchState<T>>,
+}
+
+struct WatchState<T> {
+  value: T,
+  version: u64,
+  /// One wake-up channel per receiver, holding at most one pending
+  /// version. Cleared once every sender is gone, which disconnects them.
+  watchers: Vec<Sender<u64>>,
+  senders: usize,
+}
+
+impl<T> WatchShared<T> {
+  fn read(&self) -> RwLockReadGuard<'_, WatchState<T>> {
+    self.state.read().unwrap_or_else(|e| e.into_inner())
+  }
+
+  fn write(&self) -> RwLockWriteGuard<'_, WatchState<T>> {
+    self.state.write().unwrap_or_else(|e| e.into_inner())
+  }
+
+  fn register(self: &Arc<Self>, state: &mut WatchState<T>, seen: u64) -> WatchReceiver<T> {
+    let (tx, rx) = bounded(1);
+    if state.senders > 0 {
+      if seen < state.version {
+        let _ = tx.try_send(state.version);
+      }
+      state.watchers.push(tx);
+    }
+
+    WatchReceiver {
+      shared: self.clone(),
+      notify: rx,
+      seen: AtomicU64::new(seen),
+    }
+  }
+}
+
+/// Publishing half of a [`watch`] channel. Cloning adds a sender;
+/// receivers disconnect once every sender is dropped.
+pub struct WatchSender<T>(Arc<WatchShared<T>>);
+
+impl<T> WatchSender<T> {
+  /// Replace the value and return its new version. Succeeds even with
+  /// no receivers, so later subscribers see it.
+  pub fn send(&self, value: T) -> u64 {
+    self.send_modify(|current| *current = value)
+  }
+
+  /// Update the value in place and return its new version.
+  pub fn send_modify<F>(&self, f: F) -> u64
+  where
+    F: FnOnce(&mut T),
+  {
+    let mut state = self.0.write();
+    f(&mut state.value);
+    state.version += 1;
+
+    let version = state.version;
+    state.watchers.retain(|tx| match tx.try_send(version) {
+      // Full: a wake-up is already pending.
+      Ok(()) | Err(TrySendError::Full(_)) => true,
+      Err(TrySendError::Disconnected(_)) => false,
+    });
+
+    version
+  }
+
+  /// The current value. Holding it blocks senders.
+  pub fn borrow(&self) -> WatchRef<'_, T> {
+    WatchRef(self.0.read())
+  }
+
+  /// A new receiver that has seen the current version.
+  pub fn subscribe(&self) -> WatchReceiver<T> {
+    let mut state = self.0.write();
+    let version = state.version;
+    self.0.register(&mut state, version)
+  }
+
+  pub fn receiver_count(&self) -> usize {
+    let state = self.0.read();
+    state
+      .watchers
+      .iter()
+      .filter(|tx| !tx.is_disconnected())
+      .count()
+  }
+}
+
+impl<T> Clone for WatchSender<T> {
+  fn clone(&self) -> Self {
+    self.0.write().senders += 1;
+    Self(self.0.clone())
+  }
+}
+
+impl<T> Drop for WatchSender<T> {
+  fn drop(&mut self) {
+    let mut state = self.0.write();
+    state.senders -= 1;
+    if state.senders == 0 {
+      state.watchers.clear();
+    }
+  }
+}
+
+impl<T> std::fmt::Debug for WatchSender<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<WatchSender<T>>())
+  }
+}
+
+/// Receiving half of a [`watch`] channel. Each receiver tracks which
+/// version it has seen; cloning copies that position.
+pub struct WatchReceiver<T> {
+  shared: Arc<WatchShared<T>>,
+  notify: Receiver<u64>,
+  seen: AtomicU64,
+}
+
+impl<T> WatchReceiver<T> {
+  /// The current value, without marking it seen. Holding it blocks
+  /// senders.
+  pub fn borrow(&self) -> WatchRef<'_, T> {
+    WatchRef(self.shared.read())
+  }
+
+  /// The current value, marking its version seen.
+  pub fn borrow_and_update(&self) -> WatchRef<'_, T> {
+    let state = self.shared.read();
+    self.mark(state.version);
+    WatchRef(state)
+  }
+
+  /// Version of the current value; starts at 0 and grows by one per
+  /// send.
+  pub fn version(&self) -> u64 {
+    self.shared.read().version
+  }
+
+  /// Whether a version newer than the last one seen has been published.
+  pub fn has_changed(&self) -> bool {
+    self.version() > self.seen.load(Ordering::Acquire)
+  }
+
+  /// Block until a version newer than the last one seen is published,
+  /// mark it seen and return it. Fails once every sender is gone and
+  /// the latest version has been seen.
+  pub fn changed(&self) -> Result<u64, RecvError> {
+    loop {
+      if let Some(version) = self.take_change() {
+        return Ok(version);
+      }
+      if self.notify.recv().is_err() {
+        return self.take_change().ok_or(RecvError::Disconnected);
+      }
+    }
+  }
+
+  /// Like [`changed`](Self::changed), giving up after `timeout`.
+  pub fn changed_timeout(&self, timeout: Duration) -> Result<u64, RecvTimeoutError> {
+    self.changed_deadline(Instant::now() + timeout)
+  }
+
+  /// Like [`changed`](Self::changed), giving up at `deadline`.
+  pub fn changed_deadline(&self, deadline: Instant) -> Result<u64, RecvTimeoutError> {
+    loop {
+      if let Some(version) = self.take_change() {
+        return Ok(version);
+      }
+      match self.notify.recv_deadline(deadline) {
+        Ok(_) => {}
+        Err(RecvTimeoutError::Timeout) => return Err(RecvTimeoutError::Timeout),
+        Err(RecvTimeoutError::Disconnected) => {
+          return self.take_change().ok_or(RecvTimeoutError::Disconnected);
+        }
+      }
+    }
+  }
+
+  /// Wake-up channel for `select!`: yields a version whenever one is
+  /// published and disconnects with the senders. Receiving from it does
+  /// not mark anything seen.
+  pub fn rx(&self) -> &Receiver<u64> {
+    &self.notify
+  }
+
+  fn take_change(&self) -> Option<u64> {
+    let state = self.shared.read();
+    (state.version > self.seen.load(Ordering::Acquire)).then(|| {
+      self.mark(state.version);
+      state.version
+    })
+  }
+
+  /// Mark `version` seen. Called with the state locked, so no send can
+  /// slip a wake-up in between.
+  fn mark(&self, version: u64) {
+    self.seen.fetch_max(version, Ordering::AcqRel);
+    while self.notify.try_recv().is_ok() {}
+  }
+}
+
+impl<T> Clone for WatchReceiver<T> {
+  fn clone(&self) -> Self {
+    let mut state = self.shared.write();
+    self
+      .shared
+      .register(&mut state, self.seen.load(Ordering::Acquire))
+  }
+}
+
+impl<T> AsRef<Receiver<u64>> for WatchReceiver<T> {
+  fn as_ref(&self) -> &Receiver<u64> {
+    &self.notify
+  }
+}
+
+impl<T> std::fmt::Debug for WatchReceiver<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", tynm::type_name::<WatchReceiver<T>>())
+  }
+}
+
+/// A borrowed watch value, from [`WatchReceiver::borrow`] or
+/// [`WatchSender::borrow`].
+pub struct WatchRef<'a, T>(RwLockReadGuard<'a, WatchState<T>>);
+
+impl<T> WatchRef<'_, T> {
+  /// Version of the borrowed value.
+  pub fn version(&self) -> u64 {
+    self.0.version
+  }
+}
+
+impl<T> Deref for WatchRef<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.0.value
+  }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for WatchRef<'_, T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    self.0.value.fmt(f)
+  }
+}
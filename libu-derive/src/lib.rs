@@ -193,26 +193,64 @@ pub fn clone(attr: TokenStream, item: TokenStream) -> TokenStream {
 
 /// Wait on multiple channel operations simultaneously.
 ///
-/// Expands each arm into a `flume::Selector::new().recv(...).recv(...).wait()` chain,
-/// allowing a thread to block until one of the registered channels becomes ready.
+/// Adds each arm to a flume `Selector` and waits on it, allowing a thread to
+/// block until one of the registered channels becomes ready.
+/// The expansion goes through the `libu` crate, so callers use `libu::select!`
+/// and need no direct `flume` dependency.
+///
+/// A receive arm takes anything implementing `libu::SelectRecv`: a `Chan`, a flume
+/// `Receiver`, a `WatchReceiver` (woken when the watch publishes a new version) or
+/// a `TimerRecv` from `Timer::after`/`Timer::every` (woken when the timer fires).
+/// A `send` arm takes a `Chan` or a flume `Sender`.
 ///
 /// # Syntax
 ///
 /// ```rust,ignore
+/// let tick = timer.every(Duration::from_secs(1))?;
+///
 /// select! [
-///   &rx1 => |msg| { /* handle rx1 */ },
-///   &rx2 => |msg| { /* handle rx2 */ },
+///   chan => |msg| { /* handle a message */ },
+///   tick => |_| { /* periodic work */ },
 /// ];
 /// ```
 ///
+/// # Arms
+///
+/// - `receiver => |msg| ..` - receive; `msg` is a `Result<T, RecvError>`. On a
+///   `Chan` it is `Err(RecvError::Disconnected)` once the peer has closed its
+///   sending half and the queue is drained, as with `Chan::recv`
+/// - `send(sender, value) => |res| ..` - send `value`; `res` is a `Result<(), SendError<T>>`
+/// - `timeout(duration) => || ..` - run if nothing is ready within `duration`
+/// - `deadline(instant) => || ..` - run if nothing is ready by `instant`
+/// - `default => || ..` - run if nothing is ready right away
+///
+/// At most one of `timeout`, `deadline` and `default` may appear, and at least
+/// one receive or `send` arm is required. Every handler returns the same type,
+/// which is the value of the whole `select!`.
+///
 /// # Expansion
 ///
 /// ```rust,ignore
 /// // Expands to:
-/// ::flume::Selector::new()
-///   .recv(&rx1, |msg| { /* handle rx1 */ })
-///   .recv(&rx2, |msg| { /* handle rx2 */ })
-///   .wait();
+/// ::libu::SelectRecv::select_recv(
+///   &(tick),
+///   ::libu::SelectRecv::select_recv(
+///     &(chan),
+///     ::libu::__private::Selector::new(),
+///     |msg| { /* handle a message */ },
+///   ),
+///   |_| { /* periodic work */ },
+/// )
+/// .wait();
+///
+/// // A `send(tx, value) => handler` arm adds instead:
+/// //   .send(::libu::SelectSend::select_tx(&(tx)), value, handler)
+///
+/// // With a `timeout(dur) => || fallback` arm, the chain ends instead with:
+/// match ::libu::__private::Selector::new() /* ... */ .wait_timeout(dur) {
+///   Ok(value) => value,
+///   Err(_) => (|| fallback)(),
+/// }
 /// ```
 #[proc_macro]
 pub fn select(item: TokenStream) -> TokenStream {
@@ -1,44 +1,183 @@
 use proc_macro::TokenStream;
+use proc_macro2::Span;
 use quote::quote;
 use syn::{
-  Expr, Token,
+  Error, Expr, Ident, Token,
+  ext::IdentExt,
+  parenthesized,
   parse::{Parse, ParseStream},
+  punctuated::Punctuated,
 };
 
-struct SelectInput(Vec<(Expr, Expr)>);
+enum Arm {
+  Recv {
+    receiver: Expr,
+    handler: Expr,
+  },
+  Send {
+    sender: Expr,
+    value: Expr,
+    handler: Expr,
+  },
+}
+
+/// What to do when no channel arm is ready: give up after a duration, at
+/// an instant, or right away.
+enum Fallback {
+  Timeout(Expr),
+  Deadline(Expr),
+  Default,
+}
+
+struct SelectInput {
+  arms: Vec<Arm>,
+  fallback: Option<(Fallback, Expr)>,
+}
 
 impl Parse for SelectInput {
   fn parse(input: ParseStream) -> syn::Result<Self> {
     let mut arms = Vec::new();
+    let mut fallback = None;
 
     while !input.is_empty() {
-      let receiver: Expr = input.parse()?;
+      match parse_keyword(input)? {
+        Some((keyword, args)) if keyword == "send" => {
+          let [sender, value] = <[Expr; 2]>::try_from(args.unwrap_or_default()).map_err(|_| {
+            Error::new(
+              keyword.span(),
+              "`send` arms take a sender and a value: `send(tx, value) => handler`",
+            )
+          })?;
+          let handler = parse_handler(input)?;
+          arms.push(Arm::Send {
+            sender,
+            value,
+            handler,
+          });
+        }
+        Some((keyword, args)) => {
+          let kind = parse_fallback(&keyword, args)?;
+          if fallback.is_some() {
+            return Err(Error::new(
+              keyword.span(),
+              "select! takes at most one `timeout`, `deadline` or `default` arm",
+            ));
+          }
+          fallback = Some((kind, parse_handler(input)?));
+        }
+        None => {
+          let receiver: Expr = input.parse()?;
+          let handler = parse_handler(input)?;
+          arms.push(Arm::Recv { receiver, handler });
+        }
+      }
 
-      input.parse::<Token![=>]>()?;
+      if !input.is_empty() {
+        input
+          .parse::<Token![,]>()
+          .map_err(|e| Error::new(e.span(), "expected `,` between select! arms"))?;
+      }
+    }
 
-      let handler: Expr = input.parse()?;
+    if arms.is_empty() {
+      return Err(Error::new(
+        Span::call_site(),
+        "select! needs at least one receive or `send` arm",
+      ));
+    }
 
-      input.parse::<Token![,]>();
+    Ok(SelectInput { arms, fallback })
+  }
+}
+
+/// Parse a `send(..)`, `timeout(..)`, `deadline(..)` or `default` arm
+/// head, leaving anything else to be parsed as a receiver expression.
+fn parse_keyword(input: ParseStream) -> syn::Result<Option<(Ident, Option<Vec<Expr>>)>> {
+  let fork = input.fork();
+  let Ok(keyword) = fork.call(Ident::parse_any) else {
+    return Ok(None);
+  };
 
-      arms.push((receiver, handler));
+  let args = match keyword.to_string().as_str() {
+    "default" if fork.peek(Token![=>]) => None,
+    "send" | "timeout" | "deadline" | "default" if fork.peek(syn::token::Paren) => {
+      input.call(Ident::parse_any)?;
+      let content;
+      parenthesized!(content in input);
+      let args = Punctuated::<Expr, Token![,]>::parse_terminated(&content)?;
+      return Ok(Some((keyword, Some(args.into_iter().collect()))));
     }
+    _ => return Ok(None),
+  };
+
+  input.call(Ident::parse_any)?;
+  Ok(Some((keyword, args)))
+}
+
+fn parse_handler(input: ParseStream) -> syn::Result<Expr> {
+  input
+    .parse::<Token![=>]>()
+    .map_err(|e| Error::new(e.span(), "expected `=>` followed by a handler"))?;
+
+  input.parse()
+}
 
-    Ok(SelectInput(arms))
+fn parse_fallback(keyword: &Ident, args: Option<Vec<Expr>>) -> syn::Result<Fallback> {
+  match (keyword.to_string().as_str(), args) {
+    ("timeout", Some(args)) => single(keyword, args).map(Fallback::Timeout),
+    ("deadline", Some(args)) => single(keyword, args).map(Fallback::Deadline),
+    ("default", None) => Ok(Fallback::Default),
+    _ => Err(Error::new(
+      keyword.span(),
+      "`default` takes no arguments: `default => handler`",
+    )),
   }
 }
 
+fn single(keyword: &Ident, args: Vec<Expr>) -> syn::Result<Expr> {
+  <[Expr; 1]>::try_from(args).map(|[arg]| arg).map_err(|_| {
+    Error::new(
+      keyword.span(),
+      format!("`{keyword}` takes exactly one argument"),
+    )
+  })
+}
+
 pub fn select(input: TokenStream) -> TokenStream {
-  let selects = match syn::parse::<SelectInput>(input.into()) {
+  let selects = match syn::parse::<SelectInput>(input) {
     Ok(input) => input,
     Err(e) => return e.to_compile_error().into(),
   };
 
-  let calls = selects.0.iter().map(|arm| {
-    let receiver = &arm.0;
-    let handler = &arm.1;
+  let selector = selects.arms.iter().fold(
+    quote! { ::libu::__private::Selector::new() },
+    |selector, arm| match arm {
+      Arm::Recv { receiver, handler } => {
+        quote! { ::libu::SelectRecv::select_recv(&(#receiver), #selector, #handler) }
+      }
+      Arm::Send {
+        sender,
+        value,
+        handler,
+      } => quote! { #selector.send(::libu::SelectSend::select_tx(&(#sender)), #value, #handler) },
+    },
+  );
 
-    quote! { .recv(#receiver, #handler) }
-  });
+  let (wait, handler) = match &selects.fallback {
+    None => return quote! { #selector.wait() }.into(),
+    Some((Fallback::Timeout(duration), handler)) => (quote! { .wait_timeout(#duration) }, handler),
+    Some((Fallback::Deadline(instant), handler)) => (quote! { .wait_deadline(#instant) }, handler),
+    Some((Fallback::Default, handler)) => (
+      quote! { .wait_timeout(::core::time::Duration::ZERO) },
+      handler,
+    ),
+  };
 
-  quote! { ::flume::Selector::new() #(#calls)* .wait() }.into()
+  quote! {
+    match #selector #wait {
+      ::core::result::Result::Ok(value) => value,
+      ::core::result::Result::Err(_) => (#handler)(),
+    }
+  }
+  .into()
 }
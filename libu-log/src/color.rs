@@ -0,0 +1,81 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+pub use colored::Color;
+use log::Level;
+
+/// When to color console output, for
+/// [`LogConfig::color`](crate::LogConfig::color). Files are never
+/// colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+  /// Color when stdout is a terminal and `NO_COLOR` is unset or empty.
+  #[default]
+  Auto,
+  Always,
+  Never,
+}
+
+impl ColorMode {
+  pub(crate) fn enabled(self) -> bool {
+    match self {
+      Self::Always => true,
+      Self::Never => false,
+      Self::Auto => {
+        io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+      }
+    }
+  }
+}
+
+/// The color of each level, for
+/// [`LogConfig::colors`](crate::LogConfig::colors).
+///
+/// # Example
+///
+/// ```rust
+/// use libu_log::{Color, Level, LevelColors};
+///
+/// let colors = LevelColors::default()
+///   .with(Level::Info, Color::Blue)
+///   .with(Level::Trace, Color::BrightBlack);
+///
+/// assert_eq!(colors.get(Level::Info), Color::Blue);
+/// assert_eq!(colors.get(Level::Error), Color::Red);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelColors([Color; 5]);
+
+impl LevelColors {
+  pub fn get(&self, level: Level) -> Color {
+    self.0[level as usize - 1]
+  }
+
+  pub fn set(&mut self, level: Level, color: Color) {
+    self.0[level as usize - 1] = color;
+  }
+
+  pub fn with(mut self, level: Level, color: Color) -> Self {
+    self.set(level, color);
+    self
+  }
+
+  /// `s` wrapped in the escape codes for `level`'s color.
+  pub(crate) fn paint(&self, level: Level, s: &str) -> String {
+    format!("\x1b[{}m{s}\x1b[0m", self.get(level).to_fg_str())
+  }
+}
+
+impl Default for LevelColors {
+  /// Red errors, yellow warnings, green info, cyan debug and magenta
+  /// trace.
+  fn default() -> Self {
+    Self([
+      Color::Red,
+      Color::Yellow,
+      Color::Green,
+      Color::Cyan,
+      Color::Magenta,
+    ])
+  }
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+use std::{fmt, io};
+
+use libu_derive::*;
+
+use crate::color::*;
+use crate::file::*;
+use crate::filter::*;
+use crate::format::*;
+use crate::queue::*;
+
+/// Settings for [`init_with`](crate::init_with).
+///
+/// Every field is optional; unset fields fall back to the defaults
+/// below.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_log::{LogConfig, info, init_with};
+///
+/// let directives = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_owned());
+/// let handle = init_with(LogConfig::builder().filter(directives).build()).unwrap();
+/// info!("logging at {}", handle.filter());
+///
+/// // Turn up one module without restarting.
+/// handle.set_filter("info,app::net=trace").unwrap();
+/// ```
+///
+/// Writing to a file as well as the console:
+///
+/// ```rust,no_run
+/// use libu_log::{FileSink, LogConfig};
+///
+/// let config = LogConfig::builder()
+///   .files(vec![FileSink::builder().path("app.log").daily(true).keep(7).build()])
+///   .build();
+/// ```
+///
+/// Logging from a background thread, flushing what is still queued when
+/// `main` returns:
+///
+/// ```rust,no_run
+/// use libu_log::{LogConfig, Overflow, info, init_with};
+///
+/// let config = LogConfig::builder()
+///   .queue(4096)
+///   .overflow(Overflow::Count)
+///   .build();
+/// let handle = init_with(config).unwrap();
+/// let _guard = handle.flush_guard();
+///
+/// info!("written by the writer thread");
+/// ```
+#[derive(Clone, Debug, Default, Builder)]
+pub struct LogConfig {
+  /// Which records to log, as [`LogFilter`] directives. Defaults to
+  /// `trace`, logging everything.
+  #[builder(into)]
+  pub filter: Option<String>,
+  /// Print records to stdout. Defaults to `true`.
+  pub console: Option<bool>,
+  /// When to color the console. Defaults to [`ColorMode::Auto`].
+  pub color: Option<ColorMode>,
+  /// Console colors per level. Defaults to [`LevelColors::default`].
+  pub colors: Option<LevelColors>,
+  /// Files to also write records to, uncolored.
+  pub files: Vec<FileSink>,
+  /// How records are written, to the console and files alike. Defaults
+  /// to [`LogFormat::Text`].
+  pub format: Option<LogFormat>,
+  /// Log asynchronously: hand records to a writer thread through a
+  /// queue of this many records, so the logging thread only captures
+  /// them. Off by default.
+  pub queue: Option<usize>,
+  /// What to do when the queue is full. Defaults to
+  /// [`Overflow::Block`].
+  pub overflow: Option<Overflow>,
+}
+
+impl LogConfig {
+  pub(crate) fn filter(&self) -> Result<LogFilter, LogFilterError> {
+    match &self.filter {
+      Some(directives) => directives.parse(),
+      None => Ok(LogFilter::default()),
+    }
+  }
+}
+
+/// Why [`init_with`](crate::init_with) failed.
+#[derive(Debug)]
+pub enum LogError {
+  Filter(LogFilterError),
+  /// A log file could not be opened.
+  File(PathBuf, io::Error),
+  /// A logger was already installed, by this crate or another.
+  AlreadyInitialized,
+}
+
+impl fmt::Display for LogError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Filter(e) => e.fmt(f),
+      Self::File(path, e) => write!(f, "cannot open log file {}: {e}", path.display()),
+      Self::AlreadyInitialized => write!(f, "a logger is already installed"),
+    }
+  }
+}
+
+impl std::error::Error for LogError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Filter(e) => Some(e),
+      Self::File(_, e) => Some(e),
+      Self::AlreadyInitialized => None,
+    }
+  }
+}
+
+impl From<LogFilterError> for LogError {
+  fn from(e: LogFilterError) -> Self {
+    Self::Filter(e)
+  }
+}
@@ -0,0 +1,130 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::SystemTime;
+
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, Record};
+
+/// A record captured on the logging thread, so it can be formatted
+/// later, possibly on another thread.
+pub(crate) struct Entry {
+  pub(crate) time: SystemTime,
+  pub(crate) level: Level,
+  pub(crate) target: String,
+  pub(crate) module: Option<Cow<'static, str>>,
+  pub(crate) file: Option<Cow<'static, str>>,
+  pub(crate) line: Option<u32>,
+  pub(crate) thread: Option<String>,
+  pub(crate) thread_id: u64,
+  pub(crate) msg: String,
+  pub(crate) kv: Vec<(String, Field)>,
+}
+
+/// A key-value's value, keeping numbers and booleans apart from text.
+pub(crate) enum Field {
+  Bool(bool),
+  I64(i64),
+  U64(u64),
+  F64(f64),
+  Str(String),
+}
+
+impl Entry {
+  pub(crate) fn capture(record: &Record) -> Self {
+    let (thread, thread_id) = THREAD.with(|(name, id)| (name.clone(), *id));
+    let mut kv = Fields(Vec::new());
+    let _ = record.key_values().visit(&mut kv);
+
+    Self {
+      time: SystemTime::now(),
+      level: record.level(),
+      target: record.target().to_owned(),
+      module: owned(record.module_path_static(), record.module_path()),
+      file: owned(record.file_static(), record.file()),
+      line: record.line(),
+      thread,
+      thread_id,
+      msg: match record.args().as_str() {
+        Some(msg) => msg.to_owned(),
+        None => record.args().to_string(),
+      },
+      kv: kv.0,
+    }
+  }
+}
+
+fn owned(fixed: Option<&'static str>, any: Option<&str>) -> Option<Cow<'static, str>> {
+  match fixed {
+    Some(s) => Some(Cow::Borrowed(s)),
+    None => any.map(|s| Cow::Owned(s.to_owned())),
+  }
+}
+
+/// The number the next thread to log gets. `ThreadId` has no stable
+/// integer accessor, so threads are numbered from 1 in the order they
+/// first log.
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+  /// This thread's name and number, worked out on its first record.
+  static THREAD: (Option<String>, u64) = (
+    thread::current().name().map(str::to_owned),
+    NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed),
+  );
+}
+
+struct Fields(Vec<(String, Field)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+  fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+    let field = if let Some(v) = value.to_bool() {
+      Field::Bool(v)
+    } else if let Some(v) = value.to_i64() {
+      Field::I64(v)
+    } else if let Some(v) = value.to_u64() {
+      Field::U64(v)
+    } else if let Some(v) = value.to_f64() {
+      Field::F64(v)
+    } else if let Some(v) = value.to_borrowed_str() {
+      Field::Str(v.to_owned())
+    } else {
+      Field::Str(value.to_string())
+    };
+
+    self.0.push((key.as_str().to_owned(), field));
+    Ok(())
+  }
+}
+
+impl fmt::Display for Field {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Bool(v) => v.fmt(f),
+      Self::I64(v) => v.fmt(f),
+      Self::U64(v) => v.fmt(f),
+      Self::F64(v) => v.fmt(f),
+      Self::Str(v) => v.fmt(f),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn thread_id() -> u64 {
+    Entry::capture(&Record::builder().build()).thread_id
+  }
+
+  #[test]
+  fn threads_keep_distinct_ids() {
+    let mine = thread_id();
+    assert_eq!(thread_id(), mine);
+
+    let other = thread::spawn(thread_id).join().unwrap();
+    assert_ne!(other, mine);
+    assert!(mine > 0 && other > 0);
+  }
+}
@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, SyncSender, channel, sync_channel};
+use std::thread;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use libu_derive::*;
+
+/// A log file, for [`LogConfig::files`](crate::LogConfig::files).
+///
+/// Records are appended to `path`. When the file is rotated it is
+/// renamed to `<path>.<date>.<n>`, where `date` is the day it was
+/// started and `n` counts rotations on that day, and a fresh `path` is
+/// started.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_log::FileSink;
+///
+/// // At most 10 MiB per file and one file per day, keeping the last
+/// // week of rotated files gzipped.
+/// let sink = FileSink::builder()
+///   .path("/var/log/app/app.log")
+///   .max_size(10 << 20)
+///   .daily(true)
+///   .keep(7)
+///   .compress(true)
+///   .build();
+/// ```
+#[derive(Clone, Debug, Builder)]
+pub struct FileSink {
+  #[builder(into, must)]
+  pub path: PathBuf,
+  /// Rotate before a record would take the file past this many bytes.
+  pub max_size: Option<u64>,
+  /// Rotate on the first record of a new local day.
+  pub daily: bool,
+  /// How many rotated files to keep, deleting the oldest. Keeps them
+  /// all by default.
+  pub keep: Option<usize>,
+  /// Gzip rotated files, adding `.gz` to their names. Compression runs
+  /// on a thread of its own; flushing the logger waits for it.
+  pub compress: bool,
+}
+
+/// The open file of a [`FileSink`], rotating it as records come in.
+pub(crate) struct RollingFile {
+  sink: FileSink,
+  file: File,
+  size: u64,
+  /// The day the current file was started.
+  date: NaiveDate,
+  /// Set if `sink.compress` is.
+  compressor: Option<Compressor>,
+}
+
+impl RollingFile {
+  pub(crate) fn open(sink: FileSink) -> io::Result<Self> {
+    if let Some(dir) = sink.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+      fs::create_dir_all(dir)?;
+    }
+
+    let file = append(&sink.path)?;
+    let meta = file.metadata()?;
+    // An existing file belongs to the day it was last written.
+    let date = match meta.modified() {
+      Ok(modified) if meta.len() > 0 => local_date(modified),
+      _ => Local::now().date_naive(),
+    };
+
+    let compressor = if sink.compress {
+      Some(Compressor::start(sink.clone())?)
+    } else {
+      None
+    };
+    if let Some(compressor) = &compressor {
+      // Finish what an earlier run left uncompressed.
+      for path in rotations(&sink).into_values().flatten() {
+        if path.extension().is_none_or(|ext| ext != "gz") {
+          compressor.push(path);
+        }
+      }
+    }
+
+    Ok(Self {
+      size: meta.len(),
+      sink,
+      file,
+      date,
+      compressor,
+    })
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.sink.path
+  }
+
+  /// Append one formatted record, rotating first if it is due.
+  pub(crate) fn write(&mut self, line: &[u8]) -> io::Result<()> {
+    let today = Local::now().date_naive();
+    let new_day = self.sink.daily && today != self.date;
+    let full = self
+      .sink
+      .max_size
+      .is_some_and(|max| self.size > 0 && self.size + line.len() as u64 > max);
+
+    if new_day || full {
+      self.rotate(today)?;
+    }
+
+    self.file.write_all(line)?;
+    self.size += line.len() as u64;
+    Ok(())
+  }
+
+  /// Flush the file and wait for pending compression.
+  pub(crate) fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+    if let Some(compressor) = &self.compressor {
+      compressor.wait();
+    }
+    Ok(())
+  }
+
+  fn rotate(&mut self, today: NaiveDate) -> io::Result<()> {
+    let rotated = self.next_rotated_path();
+    fs::rename(&self.sink.path, &rotated)?;
+
+    self.file = append(&self.sink.path)?;
+    self.size = 0;
+    self.date = today;
+
+    match &self.compressor {
+      Some(compressor) => compressor.push(rotated),
+      None => prune(&self.sink),
+    }
+
+    Ok(())
+  }
+
+  /// `<path>.<date>.<n>`, numbering on from the day's last rotation so
+  /// that names order rotations even after older ones are pruned.
+  fn next_rotated_path(&self) -> PathBuf {
+    let date = self.date.format("%F").to_string();
+    let n = rotations(&self.sink)
+      .range((date.clone(), 0)..=(date.clone(), u64::MAX))
+      .next_back()
+      .map_or(1, |((_, n), _)| n + 1);
+
+    PathBuf::from(format!("{}.{date}.{n}", self.sink.path.display()))
+  }
+}
+
+enum Job {
+  Gzip(PathBuf),
+  /// Reply once every earlier job is done.
+  Wait(SyncSender<()>),
+}
+
+/// The gzip thread of one [`RollingFile`]. Rotated files are compressed
+/// one at a time, in order, and pruned after each, so pruning never
+/// races a compression in flight.
+struct Compressor {
+  tx: Sender<Job>,
+}
+
+impl Compressor {
+  fn start(sink: FileSink) -> io::Result<Self> {
+    let (tx, rx) = channel();
+
+    thread::Builder::new()
+      .name("libu-log-gzip".to_owned())
+      .spawn(move || {
+        // Ends once the file, and with it the sender, is dropped.
+        for job in rx {
+          match job {
+            Job::Gzip(path) => {
+              // NotFound: pruned before its turn came.
+              if let Err(e) = gzip(&path)
+                && e.kind() != ErrorKind::NotFound
+              {
+                eprintln!("libu-log: failed to compress {}: {e}", path.display());
+              }
+              prune(&sink);
+            }
+            Job::Wait(done) => {
+              let _ = done.send(());
+            }
+          }
+        }
+      })?;
+
+    Ok(Self { tx })
+  }
+
+  fn push(&self, path: PathBuf) {
+    let _ = self.tx.send(Job::Gzip(path));
+  }
+
+  /// Block until everything pushed so far is compressed.
+  fn wait(&self) {
+    let (done, wait) = sync_channel(1);
+    if self.tx.send(Job::Wait(done)).is_ok() {
+      let _ = wait.recv();
+    }
+  }
+}
+
+impl Drop for Compressor {
+  fn drop(&mut self) {
+    self.wait();
+  }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+  OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn local_date(time: SystemTime) -> NaiveDate {
+  DateTime::<Local>::from(time).date_naive()
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(".gz");
+  PathBuf::from(name)
+}
+
+/// Replace `path` with a gzipped `<path>.gz`. The archive is written
+/// as `<path>.gz.tmp` and renamed once complete, so a `.gz` is never
+/// partial.
+fn gzip(path: &Path) -> io::Result<()> {
+  let gz = gz_path(path);
+  let mut tmp = gz.clone().into_os_string();
+  tmp.push(".tmp");
+
+  let mut input = File::open(path)?;
+  let mut output = GzEncoder::new(File::create(&tmp)?, Compression::default());
+  io::copy(&mut input, &mut output)?;
+  output.finish()?.sync_all()?;
+  fs::rename(&tmp, &gz)?;
+  fs::remove_file(path)
+}
+
+/// Delete the oldest rotations of `sink` beyond its `keep` count.
+fn prune(sink: &FileSink) {
+  let Some(keep) = sink.keep else {
+    return;
+  };
+
+  let rotations = rotations(sink);
+  let excess = rotations.len().saturating_sub(keep);
+  for path in rotations.into_values().take(excess).flatten() {
+    if let Err(e) = fs::remove_file(&path) {
+      eprintln!("libu-log: failed to remove {}: {e}", path.display());
+    }
+  }
+}
+
+/// The rotated files of `sink`, oldest first, keyed by the date and
+/// number in their names. A rotation's raw file and its `.gz` share an
+/// entry; `.gz.tmp` files being written are left out.
+fn rotations(sink: &FileSink) -> BTreeMap<(String, u64), Vec<PathBuf>> {
+  let mut rotations = BTreeMap::<_, Vec<_>>::new();
+
+  let Some(name) = sink.path.file_name().and_then(|name| name.to_str()) else {
+    return rotations;
+  };
+  let prefix = format!("{name}.");
+  let dir = match sink.path.parent() {
+    Some(dir) if !dir.as_os_str().is_empty() => dir,
+    _ => Path::new("."),
+  };
+  let Ok(entries) = fs::read_dir(dir) else {
+    return rotations;
+  };
+
+  for entry in entries.filter_map(Result::ok) {
+    let file_name = entry.file_name();
+    let Some(rest) = file_name.to_str().and_then(|n| n.strip_prefix(&prefix)) else {
+      continue;
+    };
+    let rest = rest.strip_suffix(".gz").unwrap_or(rest);
+    let Some((date, n)) = rest.rsplit_once('.') else {
+      continue;
+    };
+    if !date.starts_with(|c: char| c.is_ascii_digit()) {
+      continue;
+    }
+    if let Ok(n) = n.parse() {
+      rotations
+        .entry((date.to_owned(), n))
+        .or_default()
+        .push(entry.path());
+    }
+  }
+
+  rotations
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use flate2::read::GzDecoder;
+
+  use super::*;
+
+  /// File names in `dir`, sorted.
+  fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+      .collect();
+    names.sort();
+    names
+  }
+
+  /// Open `sink` and write `lines` seven-byte lines. With `max_size(10)`
+  /// each line ends up in a file of its own.
+  fn write_lines(sink: FileSink, lines: usize) -> RollingFile {
+    let mut file = RollingFile::open(sink).unwrap();
+    for i in 0..lines {
+      file.write(format!("line {i}\n").as_bytes()).unwrap();
+    }
+    file
+  }
+
+  fn today() -> String {
+    Local::now().date_naive().format("%F").to_string()
+  }
+
+  #[test]
+  fn size_rotation_names_files_by_date_and_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let sink = FileSink::builder().path(&path).max_size(10).build();
+
+    let mut file = write_lines(sink, 3);
+    file.flush().unwrap();
+
+    let day = today();
+    assert_eq!(
+      names(dir.path()),
+      [
+        "app.log".to_owned(),
+        format!("app.log.{day}.1"),
+        format!("app.log.{day}.2")
+      ]
+    );
+    let rotated = dir.path().join(format!("app.log.{day}.1"));
+    assert_eq!(fs::read_to_string(rotated).unwrap(), "line 0\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "line 2\n");
+  }
+
+  #[test]
+  fn rotation_prunes_to_keep() {
+    let dir = tempfile::tempdir().unwrap();
+    let sink = FileSink::builder()
+      .path(dir.path().join("app.log"))
+      .max_size(10)
+      .keep(2)
+      .build();
+
+    let mut file = write_lines(sink, 5);
+    file.flush().unwrap();
+
+    let day = today();
+    assert_eq!(
+      names(dir.path()),
+      [
+        "app.log".to_owned(),
+        format!("app.log.{day}.3"),
+        format!("app.log.{day}.4")
+      ]
+    );
+  }
+
+  #[test]
+  fn flush_waits_for_compression() {
+    let dir = tempfile::tempdir().unwrap();
+    let sink = FileSink::builder()
+      .path(dir.path().join("app.log"))
+      .max_size(10)
+      .keep(2)
+      .compress(true)
+      .build();
+
+    let mut file = write_lines(sink, 5);
+    file.flush().unwrap();
+
+    // Only finished archives are left: no raw rotations, no `.gz.tmp`.
+    let day = today();
+    assert_eq!(
+      names(dir.path()),
+      [
+        "app.log".to_owned(),
+        format!("app.log.{day}.3.gz"),
+        format!("app.log.{day}.4.gz"),
+      ]
+    );
+
+    let gz = File::open(dir.path().join(format!("app.log.{day}.4.gz"))).unwrap();
+    let mut text = String::new();
+    GzDecoder::new(gz).read_to_string(&mut text).unwrap();
+    assert_eq!(text, "line 3\n");
+  }
+}
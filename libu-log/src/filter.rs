@@ -0,0 +1,146 @@
+use std::fmt;
+use std::str::FromStr;
+
+use log::{Level, LevelFilter};
+
+/// Which records to log, parsed from an `env_logger`-style directive
+/// string such as `info,mycrate::net=debug`.
+///
+/// Directives are separated by commas:
+///
+/// - a bare level (`info`) sets the default level;
+/// - `target=level` sets the level of a target and every module below
+///   it, so `mycrate=debug` also covers `mycrate::net`;
+/// - a bare target (`mycrate::net`) logs everything under it.
+///
+/// The most specific matching target wins, and a later directive for
+/// the same target replaces an earlier one. Without a bare level only
+/// errors are logged by default. Levels are case-insensitive: `off`,
+/// `error`, `warn`, `info`, `debug` and `trace`.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_log::{Level, LogFilter};
+///
+/// let filter: LogFilter = "info,app::net=debug,app::net::tls=off".parse().unwrap();
+///
+/// assert!(filter.enabled("app", Level::Info));
+/// assert!(!filter.enabled("app", Level::Debug));
+/// assert!(filter.enabled("app::net::http", Level::Debug));
+/// assert!(!filter.enabled("app::net::tls", Level::Error));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter {
+  default: LevelFilter,
+  /// `(target, level)` in the order given, one per target.
+  directives: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+  /// A filter logging everything up to `level`, whatever the target.
+  pub fn new(level: LevelFilter) -> Self {
+    Self {
+      default: level,
+      directives: Vec::new(),
+    }
+  }
+
+  pub fn enabled(&self, target: &str, level: Level) -> bool {
+    level <= self.level_for(target)
+  }
+
+  /// The most verbose level any target can log at, for
+  /// `log::set_max_level`.
+  pub fn max_level(&self) -> LevelFilter {
+    self
+      .directives
+      .iter()
+      .map(|(_, level)| *level)
+      .fold(self.default, Ord::max)
+  }
+
+  fn level_for(&self, target: &str) -> LevelFilter {
+    self
+      .directives
+      .iter()
+      .filter(|(name, _)| covers(name, target))
+      .max_by_key(|(name, _)| name.len())
+      .map_or(self.default, |(_, level)| *level)
+  }
+}
+
+impl Default for LogFilter {
+  /// Everything, as `init` has always logged.
+  fn default() -> Self {
+    Self::new(LevelFilter::Trace)
+  }
+}
+
+/// Whether the directive for `name` applies to `target`: the same
+/// module, or one nested below it.
+fn covers(name: &str, target: &str) -> bool {
+  target
+    .strip_prefix(name)
+    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+impl FromStr for LogFilter {
+  type Err = LogFilterError;
+
+  fn from_str(s: &str) -> Result<Self, LogFilterError> {
+    let mut filter = Self::new(LevelFilter::Error);
+
+    for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+      let (target, level) = match directive.split_once('=') {
+        Some((target, level)) => {
+          let level = level
+            .trim()
+            .parse()
+            .map_err(|_| LogFilterError(directive.to_owned()))?;
+          (target.trim(), level)
+        }
+        None => match directive.parse() {
+          Ok(level) => {
+            filter.default = level;
+            continue;
+          }
+          Err(_) => (directive, LevelFilter::Trace),
+        },
+      };
+
+      if target.is_empty() || target.contains(['=', ' ', '/']) {
+        return Err(LogFilterError(directive.to_owned()));
+      }
+
+      filter.directives.retain(|(name, _)| name != target);
+      filter.directives.push((target.to_owned(), level));
+    }
+
+    Ok(filter)
+  }
+}
+
+impl fmt::Display for LogFilter {
+  /// The directive string this filter parses back from.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.default.as_str().to_lowercase())?;
+    for (target, level) in &self.directives {
+      write!(f, ",{target}={}", level.as_str().to_lowercase())?;
+    }
+
+    Ok(())
+  }
+}
+
+/// A directive [`LogFilter`] could not parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilterError(pub String);
+
+impl fmt::Display for LogFilterError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid log directive `{}`", self.0)
+  }
+}
+
+impl std::error::Error for LogFilterError {}
@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use log::Level;
+
+use crate::color::*;
+use crate::entry::*;
+use crate::template::*;
+
+/// How records are written, for [`LogConfig::format`](crate::LogConfig::format).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+  /// `[HH:MM:SS LVL]: msg. <file:line>`, colored on the console.
+  #[default]
+  Text,
+  /// One JSON object per line, for log aggregators:
+  ///
+  /// ```text
+  /// {"time":"2025-01-31T09:30:00.123456+01:00","level":"INFO","target":"app::net",
+  ///  "module":"app::net","file":"src/net.rs","line":42,"thread":"main",
+  ///  "thread_id":1,"msg":"connected","fields":{"peer":"10.0.0.7","retries":2}}
+  /// ```
+  ///
+  /// `fields` holds the record's key-values, as in
+  /// `info!(peer = addr.as_str(), retries = 2; "connected")`, and is
+  /// left out when there are none. Numbers and booleans stay JSON
+  /// numbers and booleans; anything else is written as a string.
+  /// `thread` is `null` for unnamed threads; `thread_id` numbers threads
+  /// from 1 in the order they first log.
+  Json,
+  /// A layout of your own, see [`Template`].
+  Template(Template),
+}
+
+impl LogFormat {
+  /// `entry` as one line, without the trailing newline. Colored with
+  /// `colors`, if given.
+  pub(crate) fn render(&self, entry: &Entry, colors: Option<&LevelColors>) -> String {
+    let time = DateTime::<Local>::from(entry.time);
+
+    match self {
+      Self::Text => {
+        let tag = match entry.level {
+          Level::Error => "ERR",
+          Level::Warn => "WAR",
+          Level::Info => "INF",
+          Level::Debug => "DBG",
+          Level::Trace => "TRC",
+        };
+        let prefix = format!("[{} {tag}]", time.format("%H:%M:%S"));
+        let prefix = match colors {
+          Some(colors) => colors.paint(entry.level, &prefix),
+          None => prefix,
+        };
+
+        format!(
+          "{prefix}: {}. <{}:{}>",
+          entry.msg,
+          entry.file.as_deref().unwrap_or("???"),
+          entry.line.unwrap_or(0)
+        )
+      }
+      Self::Json => json(entry, &time),
+      Self::Template(template) => template.render(entry, &time, colors),
+    }
+  }
+}
+
+/// `entry` as a JSON line, without the trailing newline.
+fn json(entry: &Entry, time: &DateTime<Local>) -> String {
+  let mut out = String::with_capacity(256);
+
+  out.push_str("{\"time\":");
+  string(
+    &mut out,
+    &time.to_rfc3339_opts(SecondsFormat::Micros, false),
+  );
+  out.push_str(",\"level\":");
+  string(&mut out, entry.level.as_str());
+  out.push_str(",\"target\":");
+  string(&mut out, &entry.target);
+  out.push_str(",\"module\":");
+  opt_string(&mut out, entry.module.as_deref());
+  out.push_str(",\"file\":");
+  opt_string(&mut out, entry.file.as_deref());
+  match entry.line {
+    Some(line) => write!(out, ",\"line\":{line}").unwrap(),
+    None => out.push_str(",\"line\":null"),
+  }
+  out.push_str(",\"thread\":");
+  opt_string(&mut out, entry.thread.as_deref());
+  write!(out, ",\"thread_id\":{}", entry.thread_id).unwrap();
+  out.push_str(",\"msg\":");
+  string(&mut out, &entry.msg);
+
+  if !entry.kv.is_empty() {
+    out.push_str(",\"fields\":{");
+    for (i, (key, value)) in entry.kv.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      string(&mut out, key);
+      out.push(':');
+
+      match value {
+        Field::Str(v) => string(&mut out, v),
+        Field::F64(v) if !v.is_finite() => string(&mut out, &v.to_string()),
+        v => write!(out, "{v}").unwrap(),
+      }
+    }
+    out.push('}');
+  }
+
+  out.push('}');
+  out
+}
+
+fn opt_string(out: &mut String, s: Option<&str>) {
+  match s {
+    Some(s) => string(out, s),
+    None => out.push_str("null"),
+  }
+}
+
+/// Append `s` as a quoted, escaped JSON string.
+fn string(out: &mut String, s: &str) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
@@ -3,12 +3,54 @@
 
 pub use log::*;
 
+mod color;
+mod config;
+mod entry;
+mod file;
+mod filter;
+mod format;
 mod logger;
+mod queue;
+mod template;
 
-static LOGGER: logger::Logger = logger::Logger {
-  level: Level::Trace,
-};
+pub use color::*;
+pub use config::*;
+pub use file::FileSink;
+pub use filter::*;
+pub use format::LogFormat;
+pub use logger::{LogGuard, LogHandle};
+pub use queue::Overflow;
+pub use template::*;
 
+/// Install the logger, logging everything. Does nothing if a logger is
+/// already installed.
 pub fn init() {
-  let _ = set_logger(&LOGGER).map(|()| set_max_level(LevelFilter::Trace));
+  let _ = init_with(LogConfig::default());
+}
+
+/// Install the logger configured by `config`, returning a handle to
+/// change its settings at runtime.
+///
+/// # Panics
+///
+/// Panics if [`LogConfig::queue`] is set and the writer thread cannot
+/// be spawned.
+pub fn init_with(mut config: LogConfig) -> Result<LogHandle, LogError> {
+  let filter = config.filter()?;
+  let max_level = filter.max_level();
+
+  let files = std::mem::take(&mut config.files)
+    .into_iter()
+    .map(|sink| {
+      let path = sink.path.clone();
+      file::RollingFile::open(sink).map_err(|e| LogError::File(path, e))
+    })
+    .collect::<Result<_, _>>()?;
+
+  let shared = logger::Shared::new(filter, config, files);
+  set_boxed_logger(Box::new(logger::Logger(shared.clone())))
+    .map_err(|_| LogError::AlreadyInitialized)?;
+  set_max_level(max_level);
+
+  Ok(LogHandle(shared))
 }
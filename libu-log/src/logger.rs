@@ -1,33 +1,200 @@
-use colored::Colorize;
-use log::Level;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
 
-pub struct Logger {
-  pub(crate) level: Level,
+use crate::color::*;
+use crate::config::*;
+use crate::entry::*;
+use crate::file::*;
+use crate::filter::*;
+use crate::format::*;
+use crate::queue::*;
+
+/// Where records end up: the console and any files.
+pub(crate) struct Sink {
+  format: LogFormat,
+  console: bool,
+  /// Console colors, if the console is colored.
+  colors: Option<LevelColors>,
+  files: Vec<Mutex<RollingFile>>,
+}
+
+impl Sink {
+  pub(crate) fn write(&self, entry: &Entry) {
+    let colors = self.colors.as_ref().filter(|_| self.console);
+    let mut line = self.format.render(entry, colors);
+
+    if self.console {
+      let _ = writeln!(io::stdout().lock(), "{line}");
+    }
+
+    if !self.files.is_empty() {
+      if colors.is_some() {
+        line = self.format.render(entry, None);
+      }
+      line.push('\n');
+
+      for file in &self.files {
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write(line.as_bytes()) {
+          eprintln!("libu-log: failed to write {}: {e}", file.path().display());
+        }
+      }
+    }
+  }
+
+  pub(crate) fn flush(&self) {
+    if self.console {
+      let _ = io::stdout().lock().flush();
+    }
+
+    for file in &self.files {
+      let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+      if let Err(e) = file.flush() {
+        eprintln!("libu-log: failed to flush {}: {e}", file.path().display());
+      }
+    }
+  }
 }
 
+#[cfg(test)]
+impl Sink {
+  /// Plain text to `file` alone.
+  pub(crate) fn file(file: RollingFile) -> Self {
+    Self {
+      format: LogFormat::Text,
+      console: false,
+      colors: None,
+      files: vec![Mutex::new(file)],
+    }
+  }
+
+  /// Hold the file, stalling every write until the guard is dropped.
+  pub(crate) fn stall(&self) -> std::sync::MutexGuard<'_, RollingFile> {
+    self.files[0].lock().unwrap()
+  }
+}
+
+pub(crate) struct Shared {
+  filter: RwLock<LogFilter>,
+  sink: Arc<Sink>,
+  /// Set in asynchronous mode, where `sink` is written by another thread.
+  queue: Option<Queue>,
+}
+
+impl Shared {
+  pub(crate) fn new(filter: LogFilter, config: LogConfig, files: Vec<RollingFile>) -> Arc<Self> {
+    let colored = config.color.unwrap_or_default().enabled();
+    let sink = Arc::new(Sink {
+      format: config.format.unwrap_or_default(),
+      console: config.console.unwrap_or(true),
+      colors: colored.then(|| config.colors.unwrap_or_default()),
+      files: files.into_iter().map(Mutex::new).collect(),
+    });
+    let queue = config
+      .queue
+      .map(|capacity| Queue::start(sink.clone(), capacity, config.overflow.unwrap_or_default()));
+
+    Arc::new(Self {
+      filter: RwLock::new(filter),
+      sink,
+      queue,
+    })
+  }
+
+  fn filter(&self) -> RwLockReadGuard<'_, LogFilter> {
+    self.filter.read().unwrap_or_else(|e| e.into_inner())
+  }
+
+  fn flush(&self) {
+    match &self.queue {
+      Some(queue) => queue.flush(),
+      None => self.sink.flush(),
+    }
+  }
+}
+
+pub(crate) struct Logger(pub(crate) Arc<Shared>);
+
 impl log::Log for Logger {
   fn enabled(&self, metadata: &log::Metadata) -> bool {
-    metadata.level() <= self.level
+    self.0.filter().enabled(metadata.target(), metadata.level())
   }
 
   fn log(&self, record: &log::Record) {
     if self.enabled(record.metadata()) {
-      let time = chrono::Local::now();
-      let args = record.args();
-      let line = record.line().unwrap_or(0);
-      let file = record.file().unwrap_or("???");
-
-      let prefix = match record.level() {
-        Level::Error => format!("[{} ERR]", time.format("%H:%M:%S")).red(),
-        Level::Warn => format!("[{} WAR]", time.format("%H:%M:%S")).yellow(),
-        Level::Info => format!("[{} INF]", time.format("%H:%M:%S")).green(),
-        Level::Debug => format!("[{} DBG]", time.format("%H:%M:%S")).cyan(),
-        Level::Trace => format!("[{} TRC]", time.format("%H:%M:%S")).purple(),
-      };
-
-      println!("{prefix}: {}. <{}:{}>", args, file, line);
+      let entry = Entry::capture(record);
+
+      match &self.0.queue {
+        Some(queue) => queue.push(entry),
+        None => self.0.sink.write(&entry),
+      }
     }
   }
 
-  fn flush(&self) {}
+  fn flush(&self) {
+    self.0.flush();
+  }
+}
+
+/// Runtime control over the logger installed by
+/// [`init_with`](crate::init_with). Cheap to clone.
+#[derive(Clone)]
+pub struct LogHandle(pub(crate) Arc<Shared>);
+
+impl LogHandle {
+  /// The current filter, as directives.
+  pub fn filter(&self) -> String {
+    self.0.filter().to_string()
+  }
+
+  /// Replace the filter with `directives`, see [`LogFilter`]. The old
+  /// filter stays in place if they do not parse.
+  pub fn set_filter(&self, directives: &str) -> Result<(), LogFilterError> {
+    self.set_log_filter(directives.parse()?);
+    Ok(())
+  }
+
+  pub fn set_log_filter(&self, filter: LogFilter) {
+    let mut current = self.0.filter.write().unwrap_or_else(|e| e.into_inner());
+    log::set_max_level(filter.max_level());
+    *current = filter;
+  }
+
+  /// Block until every record logged so far is written out. In
+  /// asynchronous mode this waits for the writer thread to catch up.
+  pub fn flush(&self) {
+    self.0.flush();
+  }
+
+  /// A guard that calls [`flush`](Self::flush) when dropped. Keep one
+  /// alive until the end of `main`, so queued records are not lost on
+  /// exit.
+  pub fn flush_guard(&self) -> LogGuard {
+    LogGuard(self.clone())
+  }
+
+  /// Records discarded so far because the queue was full, with
+  /// [`Overflow::Drop`] or [`Overflow::Count`].
+  pub fn dropped(&self) -> u64 {
+    self.0.queue.as_ref().map_or(0, Queue::dropped)
+  }
+}
+
+/// Flushes the logger when dropped, from [`LogHandle::flush_guard`].
+#[must_use = "the logger is flushed when the guard is dropped"]
+#[derive(Debug)]
+pub struct LogGuard(LogHandle);
+
+impl Drop for LogGuard {
+  fn drop(&mut self) {
+    self.0.flush();
+  }
+}
+
+impl std::fmt::Debug for LogHandle {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("LogHandle")
+      .field("filter", &self.filter())
+      .finish()
+  }
 }
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TryRecvError, TrySendError, sync_channel};
+use std::thread;
+
+use log::{Level, Record};
+
+use crate::entry::*;
+use crate::logger::Sink;
+
+/// What an asynchronous logger does with a record when its queue is
+/// full, for [`LogConfig::overflow`](crate::LogConfig::overflow).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+  /// Wait for room, slowing the logging thread to the writer's pace.
+  #[default]
+  Block,
+  /// Discard the record.
+  Drop,
+  /// Discard the record, and once the writer catches up, log a warning
+  /// saying how many were discarded.
+  Count,
+}
+
+enum Msg {
+  Entry(Entry),
+  /// Write everything queued before this, then reply.
+  Flush(SyncSender<()>),
+}
+
+/// Records on their way to the writer thread.
+pub(crate) struct Queue {
+  tx: SyncSender<Msg>,
+  overflow: Overflow,
+  dropped: Arc<Dropped>,
+}
+
+#[derive(Default)]
+struct Dropped {
+  total: AtomicU64,
+  /// Dropped since the last warning, for [`Overflow::Count`].
+  unreported: AtomicU64,
+}
+
+impl Queue {
+  /// Start a writer thread draining a queue of `capacity` records into
+  /// `sink`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the writer thread cannot be spawned.
+  pub(crate) fn start(sink: Arc<Sink>, capacity: usize, overflow: Overflow) -> Self {
+    let (tx, rx) = sync_channel(capacity.max(1));
+    let dropped = Arc::new(Dropped::default());
+
+    let counts = dropped.clone();
+    thread::Builder::new()
+      .name("libu-log-writer".to_owned())
+      .spawn(move || {
+        // Ends once the logger, and with it the sender, is dropped.
+        while let Some(msg) = next(&rx, || report(&sink, overflow, &counts)) {
+          match msg {
+            Msg::Entry(entry) => sink.write(&entry),
+            Msg::Flush(done) => {
+              report(&sink, overflow, &counts);
+              sink.flush();
+              let _ = done.send(());
+            }
+          }
+        }
+      })
+      .expect("failed to spawn log writer thread");
+
+    Self {
+      tx,
+      overflow,
+      dropped,
+    }
+  }
+
+  pub(crate) fn push(&self, entry: Entry) {
+    if self.overflow == Overflow::Block {
+      let _ = self.tx.send(Msg::Entry(entry));
+      return;
+    }
+
+    if let Err(TrySendError::Full(_)) = self.tx.try_send(Msg::Entry(entry)) {
+      self.dropped.total.fetch_add(1, Ordering::Relaxed);
+      self.dropped.unreported.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  /// Block until everything queued so far is written and flushed.
+  pub(crate) fn flush(&self) {
+    let (done, wait) = sync_channel(1);
+    if self.tx.send(Msg::Flush(done)).is_ok() {
+      let _ = wait.recv();
+    }
+  }
+
+  pub(crate) fn dropped(&self) -> u64 {
+    self.dropped.total.load(Ordering::Relaxed)
+  }
+}
+
+/// The next message on `rx`, calling `idle` first if none is queued.
+/// Records dropped while the queue was full came after every queued
+/// one, so an empty queue is where their warning goes.
+fn next(rx: &Receiver<Msg>, idle: impl FnOnce()) -> Option<Msg> {
+  match rx.try_recv() {
+    Ok(msg) => Some(msg),
+    Err(TryRecvError::Empty) => {
+      idle();
+      rx.recv().ok()
+    }
+    Err(TryRecvError::Disconnected) => None,
+  }
+}
+
+/// Write the [`Overflow::Count`] warning, if records were dropped since
+/// the last one.
+fn report(sink: &Sink, overflow: Overflow, dropped: &Dropped) {
+  if overflow == Overflow::Count {
+    let n = dropped.unreported.swap(0, Ordering::Relaxed);
+    if n > 0 {
+      sink.write(&notice(n));
+    }
+  }
+}
+
+/// The warning for [`Overflow::Count`], as if logged by this crate.
+fn notice(dropped: u64) -> Entry {
+  Entry::capture(
+    &Record::builder()
+      .args(format_args!(
+        "dropped {dropped} log records, the queue was full"
+      ))
+      .level(Level::Warn)
+      .target(module_path!())
+      .module_path_static(Some(module_path!()))
+      .file_static(Some(file!()))
+      .line(Some(line!()))
+      .build(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::time::Duration;
+
+  use super::*;
+  use crate::file::{FileSink, RollingFile};
+
+  fn entry(msg: &str) -> Entry {
+    Entry::capture(&Record::builder().args(format_args!("{msg}")).build())
+  }
+
+  /// Push `a` and let the writer stall on it, then `b`, which fills the
+  /// capacity-1 queue, then `c` and `d`, which overflow. Returns the
+  /// messages written once the writer is released and flushed.
+  fn overflow(overflow: Overflow) -> (u64, Vec<String>) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let file = RollingFile::open(FileSink::builder().path(&path).build()).unwrap();
+    let sink = Arc::new(Sink::file(file));
+    let queue = Queue::start(sink.clone(), 1, overflow);
+
+    let stall = sink.stall();
+    queue.push(entry("a"));
+    // Give the writer time to take `a` and block on the file.
+    thread::sleep(Duration::from_millis(50));
+    for msg in ["b", "c", "d"] {
+      queue.push(entry(msg));
+    }
+    let dropped = queue.dropped();
+
+    drop(stall);
+    queue.flush();
+
+    let text = fs::read_to_string(&path).unwrap();
+    let lines = text.lines().map(|line| {
+      let msg = line.split_once("]: ").unwrap().1;
+      msg.rsplit_once(". <").unwrap().0.to_owned()
+    });
+    (dropped, lines.collect())
+  }
+
+  #[test]
+  fn drop_discards_silently() {
+    assert_eq!(
+      overflow(Overflow::Drop),
+      (2, vec!["a".to_owned(), "b".to_owned()])
+    );
+  }
+
+  #[test]
+  fn count_reports_after_the_queued_records() {
+    let (dropped, lines) = overflow(Overflow::Count);
+    assert_eq!(dropped, 2);
+    assert_eq!(
+      lines,
+      ["a", "b", "dropped 2 log records, the queue was full"]
+    );
+  }
+}
@@ -0,0 +1,254 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+
+use crate::color::*;
+use crate::entry::Entry;
+
+/// A record layout, for [`LogFormat::Template`](crate::LogFormat::Template).
+///
+/// Text is copied as is, and each `{field}` or `{field:spec}` is
+/// replaced by part of the record:
+///
+/// | Field | Value |
+/// |-------|-------|
+/// | `time` | Local time; the spec is a `chrono` format, `%H:%M:%S` by default |
+/// | `level` | `ERROR` to `TRACE`, colored on a color console |
+/// | `target` | The record's target |
+/// | `module` | Module path, or empty |
+/// | `file`, `line` | Source location, or `???` and `0` |
+/// | `thread` | Thread name, or empty |
+/// | `msg` | The message |
+/// | `kv` | Key-values as `key=value`, space-separated |
+///
+/// For every field but `time`, the spec pads the value to a width, to
+/// the left (`<`, the default), right (`>`) or centre (`^`): `{level:>5}`.
+/// Write `{{` and `}}` for literal braces.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_log::{LogConfig, LogFormat, Template};
+///
+/// let template: Template = "{time:%F %T%.3f} {level:>5} {target} {file}:{line} {msg}"
+///   .parse()
+///   .unwrap();
+/// let config = LogConfig::builder()
+///   .format(LogFormat::Template(template))
+///   .build();
+///
+/// assert!("{time:%Q}".parse::<Template>().is_err());
+/// assert!("{nope}".parse::<Template>().is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Template {
+  source: String,
+  pieces: Vec<Piece>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+  Text(String),
+  Time(Vec<Item<'static>>),
+  Field {
+    field: Field,
+    align: Align,
+    width: usize,
+  },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+  Level,
+  Target,
+  Module,
+  File,
+  Line,
+  Thread,
+  Msg,
+  Kv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Align {
+  Left,
+  Right,
+  Center,
+}
+
+impl Template {
+  /// `entry` laid out by this template, without a trailing newline.
+  pub(crate) fn render(
+    &self,
+    entry: &Entry,
+    time: &DateTime<Local>,
+    colors: Option<&LevelColors>,
+  ) -> String {
+    let mut out = String::with_capacity(128);
+
+    for piece in &self.pieces {
+      match piece {
+        Piece::Text(text) => out.push_str(text),
+        Piece::Time(items) => write!(out, "{}", time.format_with_items(items.iter())).unwrap(),
+        Piece::Field {
+          field,
+          align,
+          width,
+        } => {
+          let value = match field {
+            Field::Level => entry.level.as_str().to_owned(),
+            Field::Target => entry.target.clone(),
+            Field::Module => entry.module.as_deref().unwrap_or_default().to_owned(),
+            Field::File => entry.file.as_deref().unwrap_or("???").to_owned(),
+            Field::Line => entry.line.unwrap_or(0).to_string(),
+            Field::Thread => entry.thread.clone().unwrap_or_default(),
+            Field::Msg => entry.msg.clone(),
+            Field::Kv => {
+              let mut kv = String::new();
+              for (key, value) in &entry.kv {
+                if !kv.is_empty() {
+                  kv.push(' ');
+                }
+                write!(kv, "{key}={value}").unwrap();
+              }
+              kv
+            }
+          };
+          let value = match align {
+            Align::Left => format!("{value:<width$}"),
+            Align::Right => format!("{value:>width$}"),
+            Align::Center => format!("{value:^width$}"),
+          };
+
+          match colors {
+            Some(colors) if *field == Field::Level => {
+              out.push_str(&colors.paint(entry.level, &value))
+            }
+            _ => out.push_str(&value),
+          }
+        }
+      }
+    }
+
+    out
+  }
+}
+
+impl FromStr for Template {
+  type Err = TemplateError;
+
+  fn from_str(s: &str) -> Result<Self, TemplateError> {
+    let err = |msg: &str| TemplateError(msg.to_owned());
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find(['{', '}']) {
+      text.push_str(&rest[..i]);
+      let brace = &rest[i..];
+
+      if let Some(after) = brace.strip_prefix("{{") {
+        text.push('{');
+        rest = after;
+        continue;
+      }
+      if let Some(after) = brace.strip_prefix("}}") {
+        text.push('}');
+        rest = after;
+        continue;
+      }
+      if brace.starts_with('}') {
+        return Err(err("unmatched `}`, write `}}` for a literal brace"));
+      }
+
+      let end = brace.find('}').ok_or_else(|| err("unclosed `{`"))?;
+      let (name, spec) = match brace[1..end].split_once(':') {
+        Some((name, spec)) => (name.trim(), Some(spec)),
+        None => (brace[1..end].trim(), None),
+      };
+
+      if !text.is_empty() {
+        pieces.push(Piece::Text(std::mem::take(&mut text)));
+      }
+      pieces.push(parse_piece(name, spec)?);
+      rest = &brace[end + 1..];
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+      pieces.push(Piece::Text(text));
+    }
+
+    Ok(Self {
+      source: s.to_owned(),
+      pieces,
+    })
+  }
+}
+
+fn parse_piece(name: &str, spec: Option<&str>) -> Result<Piece, TemplateError> {
+  let field = match name {
+    "time" => {
+      let items = StrftimeItems::new(spec.unwrap_or("%H:%M:%S"))
+        .parse_to_owned()
+        .map_err(|_| TemplateError(format!("invalid time format `{}`", spec.unwrap_or(""))))?;
+      return Ok(Piece::Time(items));
+    }
+    "level" => Field::Level,
+    "target" => Field::Target,
+    "module" => Field::Module,
+    "file" => Field::File,
+    "line" => Field::Line,
+    "thread" => Field::Thread,
+    "msg" => Field::Msg,
+    "kv" => Field::Kv,
+    _ => return Err(TemplateError(format!("unknown field `{name}`"))),
+  };
+
+  let spec = spec.unwrap_or("").trim();
+  let (align, width) = match spec.chars().next() {
+    Some('<') => (Align::Left, &spec[1..]),
+    Some('>') => (Align::Right, &spec[1..]),
+    Some('^') => (Align::Center, &spec[1..]),
+    _ => (Align::Left, spec),
+  };
+  let width = match width {
+    "" => 0,
+    width => width
+      .parse()
+      .map_err(|_| TemplateError(format!("invalid width in `{name}:{spec}`")))?,
+  };
+
+  Ok(Piece::Field {
+    field,
+    align,
+    width,
+  })
+}
+
+impl fmt::Display for Template {
+  /// The string this template was parsed from.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.source)
+  }
+}
+
+impl fmt::Debug for Template {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("Template").field(&self.source).finish()
+  }
+}
+
+/// Why a [`Template`] could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TemplateError(pub String);
+
+impl fmt::Display for TemplateError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid log template: {}", self.0)
+  }
+}
+
+impl std::error::Error for TemplateError {}
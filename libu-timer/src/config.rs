@@ -0,0 +1,148 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use libu_derive::*;
+
+use crate::executor::*;
+use crate::stats::*;
+
+/// Settings for a [`Timer`](crate::Timer).
+///
+/// Every field is optional; unset fields fall back to the defaults
+/// below. Build one with `TimerConfig::builder()` and pass it to
+/// [`Timer::with_config`](crate::Timer::with_config), which validates it.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use libu_timer::{Timer, TimerConfig};
+///
+/// let timer = Timer::with_config(
+///   TimerConfig::builder()
+///     .tick(Duration::from_millis(10))
+///     .wheel_size(256)
+///     .thread_name("retransmit")
+///     .build(),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone, Default, Builder)]
+pub struct TimerConfig {
+  /// Duration of one tick. Defaults to 100ms, must be at least 1ms.
+  pub tick: Option<Duration>,
+  /// Buckets per wheel level. Defaults to 64, must be a power of two
+  /// between 2 and 65536.
+  pub wheel_size: Option<usize>,
+  /// Name of the worker thread. Defaults to `libu-timer`.
+  #[builder(into)]
+  pub thread_name: Option<String>,
+  /// Drive the wheel from `Timer::advance` instead of a worker thread.
+  /// Defaults to `false`.
+  pub virtual_clock: Option<bool>,
+  /// Where due callbacks run. Defaults to inline on the wheel thread;
+  /// see [`TimerPool`]. Ignored with `virtual_clock`, whose callbacks
+  /// always run inline on the thread calling `advance`.
+  pub executor: Option<Arc<dyn TimerExecutor>>,
+  /// Called with the message of every panicking callback. Defaults to
+  /// logging it at error level through `libu-log`.
+  pub panic_hook: Option<TimerPanicHook>,
+}
+
+impl fmt::Debug for TimerConfig {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("TimerConfig")
+      .field("tick", &self.tick)
+      .field("wheel_size", &self.wheel_size)
+      .field("thread_name", &self.thread_name)
+      .field("virtual_clock", &self.virtual_clock)
+      .field("executor", &self.executor.is_some())
+      .field("panic_hook", &self.panic_hook.is_some())
+      .finish()
+  }
+}
+
+impl TimerConfig {
+  pub(crate) const DEFAULT_TICK: Duration = Duration::from_millis(100);
+  pub(crate) const DEFAULT_WHEEL_SIZE: usize = 64;
+  pub(crate) const DEFAULT_THREAD_NAME: &str = "libu-timer";
+
+  /// Finer ticks than this cannot be honored by `thread::sleep`.
+  const MIN_TICK: Duration = Duration::from_millis(1);
+  /// Each level allocates `wheel_size` buckets, so keep it sane.
+  const MAX_WHEEL_SIZE: usize = 1 << 16;
+
+  pub(crate) fn tick(&self) -> Duration {
+    self.tick.unwrap_or(Self::DEFAULT_TICK)
+  }
+
+  pub(crate) fn wheel_size(&self) -> usize {
+    self.wheel_size.unwrap_or(Self::DEFAULT_WHEEL_SIZE)
+  }
+
+  pub(crate) fn thread_name(&self) -> &str {
+    self
+      .thread_name
+      .as_deref()
+      .unwrap_or(Self::DEFAULT_THREAD_NAME)
+  }
+
+  pub(crate) fn validate(&self) -> Result<(), TimerConfigError> {
+    let tick = self.tick();
+    if tick < Self::MIN_TICK {
+      return Err(TimerConfigError::TickTooSmall(tick));
+    }
+
+    let size = self.wheel_size();
+    if !size.is_power_of_two() || !(2..=Self::MAX_WHEEL_SIZE).contains(&size) {
+      return Err(TimerConfigError::InvalidWheelSize(size));
+    }
+
+    // std::thread::Builder panics on interior NUL bytes.
+    if self.thread_name().contains('\0') {
+      return Err(TimerConfigError::InvalidThreadName);
+    }
+
+    Ok(())
+  }
+}
+
+/// Why [`Timer::with_config`](crate::Timer::with_config) rejected a
+/// [`TimerConfig`].
+#[derive(Debug)]
+pub enum TimerConfigError {
+  /// The tick is shorter than 1ms.
+  TickTooSmall(Duration),
+  /// The wheel size is not a power of two in `2..=65536`.
+  InvalidWheelSize(usize),
+  /// The thread name contains a NUL byte.
+  InvalidThreadName,
+  /// The worker thread could not be spawned.
+  Spawn(std::io::Error),
+}
+
+impl fmt::Display for TimerConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::TickTooSmall(tick) => write!(f, "timer tick {tick:?} is shorter than 1ms"),
+      Self::InvalidWheelSize(size) => {
+        write!(
+          f,
+          "timer wheel size {size} is not a power of two in 2..=65536"
+        )
+      }
+      Self::InvalidThreadName => write!(f, "timer thread name contains a NUL byte"),
+      Self::Spawn(e) => write!(f, "failed to spawn timer thread: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for TimerConfigError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Spawn(e) => Some(e),
+      _ => None,
+    }
+  }
+}
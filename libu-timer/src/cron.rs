@@ -0,0 +1,334 @@
+//! Cron expressions evaluated against the local wall clock.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{
+  DateTime, Datelike, LocalResult, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike,
+};
+
+use crate::timer::*;
+
+/// Longest wait between two reads of the wall clock.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Search horizon for the next occurrence. Eight years covers a
+/// February 29 across a skipped leap year.
+const HORIZON_DAYS: i64 = 8 * 366;
+
+const MONTHS: &[&str] = &[
+  "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// A parsed cron expression.
+///
+/// # Syntax
+///
+/// Six space-separated fields, or five with the seconds field omitted
+/// (it then defaults to `0`):
+///
+/// | Field | Range | Names |
+/// |-------|-------|-------|
+/// | second | 0-59 | |
+/// | minute | 0-59 | |
+/// | hour | 0-23 | |
+/// | day of month | 1-31 | |
+/// | month | 1-12 | `JAN`-`DEC` |
+/// | day of week | 0-7 (0 and 7 are Sunday) | `SUN`-`SAT` |
+///
+/// Each field is a comma-separated list of `*` (or `?`), `N`, `A-B`,
+/// each optionally followed by `/STEP`; `N/STEP` means `N-max/STEP`.
+/// As in classic cron, when both day fields are restricted a day
+/// matching either one matches. An expression that no date matches, like
+/// `0 0 0 30 2 *`, is rejected.
+///
+/// # Daylight saving and clock jumps
+///
+/// Fire times are computed in local time with `chrono`:
+///
+/// - A local time skipped by a DST change fires when the gap ends
+///   (a 02:30 job fires at 03:00 on spring-forward day).
+/// - A local time repeated by a DST change fires once, on its first
+///   occurrence.
+/// - The timer never waits more than a minute without re-reading the
+///   wall clock, so a job is neither run early after the clock jumps
+///   back nor delayed by more than a minute after it jumps forward.
+///   Occurrences skipped by a forward jump are run once, not replayed.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_timer::Cron;
+///
+/// // Every five minutes, on the minute.
+/// let every_five: Cron = "0 */5 * * * *".parse().unwrap();
+///
+/// // Every day at 03:00 local time.
+/// let nightly: Cron = "0 0 3 * * *".parse().unwrap();
+///
+/// // Weekdays at 09:30, five-field form.
+/// let standup: Cron = "30 9 * * MON-FRI".parse().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cron {
+  /// One bit per allowed value of each field.
+  seconds: u64,
+  minutes: u64,
+  hours: u64,
+  days: u64,
+  months: u64,
+  weekdays: u64,
+  /// Day-of-month / day-of-week were `*`, which changes how the two
+  /// day fields combine.
+  any_day: bool,
+  any_weekday: bool,
+}
+
+impl Cron {
+  pub fn parse(expr: &str) -> Result<Self, CronError> {
+    let fields = expr.split_whitespace().collect::<Vec<_>>();
+    let fields = match fields.len() {
+      6 => fields,
+      5 => [&["0"], &fields[..]].concat(),
+      n => return Err(CronError(format!("expected 5 or 6 fields, got {n}"))),
+    };
+
+    // Day of week accepts 7 as an alias for Sunday.
+    let weekdays = field(fields[5], "day of week", 0, 7, WEEKDAYS)?;
+
+    let cron = Self {
+      seconds: field(fields[0], "second", 0, 59, &[])?,
+      minutes: field(fields[1], "minute", 0, 59, &[])?,
+      hours: field(fields[2], "hour", 0, 23, &[])?,
+      days: field(fields[3], "day of month", 1, 31, &[])?,
+      months: field(fields[4], "month", 1, 12, MONTHS)?,
+      weekdays: (weekdays | weekdays >> 7) & 0x7f,
+      any_day: is_any(fields[3]),
+      any_weekday: is_any(fields[5]),
+    };
+
+    if !cron.can_match() {
+      return Err(CronError("no day of the year matches".to_owned()));
+    }
+    Ok(cron)
+  }
+
+  /// The first occurrence strictly after `after`, in `after`'s time
+  /// zone. `None` if there is none within eight years, which only a DST
+  /// gap swallowing every occurrence could cause.
+  pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let tz = after.timezone();
+    let start = after.naive_local().with_nanosecond(0)? + TimeDelta::seconds(1);
+    let limit = start + TimeDelta::days(HORIZON_DAYS);
+
+    let mut naive = start;
+    while let Some(found) = self.next_naive(naive, limit) {
+      let resolved = match tz.from_local_datetime(&found) {
+        LocalResult::Single(t) => Some(t),
+        // Take the later instant only if the earlier one has passed,
+        // so a repeated local time fires once.
+        LocalResult::Ambiguous(early, late) => Some(if early > *after { early } else { late }),
+        LocalResult::None => gap_end(&tz, found),
+      };
+
+      if let Some(t) = resolved.filter(|t| t > after) {
+        return Some(t);
+      }
+
+      naive = found + TimeDelta::seconds(1);
+    }
+
+    None
+  }
+
+  /// First local time at or after `t` (and before `limit`) matching
+  /// every field, ignoring time zones.
+  fn next_naive(&self, mut t: NaiveDateTime, limit: NaiveDateTime) -> Option<NaiveDateTime> {
+    while t < limit {
+      let date = t.date();
+
+      if !has(self.months, date.month()) {
+        let (year, month) = match date.month() {
+          12 => (date.year() + 1, 1),
+          m => (date.year(), m + 1),
+        };
+        t = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+      } else if !self.day_matches(date) {
+        t = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+      } else if !has(self.hours, t.hour()) {
+        t = date.and_hms_opt(t.hour(), 0, 0)? + TimeDelta::hours(1);
+      } else if !has(self.minutes, t.minute()) {
+        t = date.and_hms_opt(t.hour(), t.minute(), 0)? + TimeDelta::minutes(1);
+      } else if !has(self.seconds, t.second()) {
+        t += TimeDelta::seconds(1);
+      } else {
+        return Some(t);
+      }
+    }
+
+    None
+  }
+
+  /// Whether some date matches. Only a day of month that no allowed
+  /// month has, like February 30, can rule out every date: each field
+  /// allows at least one value, and a restricted day of week matches
+  /// some day of every month.
+  fn can_match(&self) -> bool {
+    if !self.any_weekday {
+      return true;
+    }
+
+    // Longest length of each month, counting leap years.
+    const DAYS: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    (1..=12).any(|month| {
+      has(self.months, month) && (1..=DAYS[month as usize - 1]).any(|day| has(self.days, day))
+    })
+  }
+
+  fn day_matches(&self, date: NaiveDate) -> bool {
+    let day = has(self.days, date.day());
+    let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+
+    if self.any_day || self.any_weekday {
+      day && weekday
+    } else {
+      day || weekday
+    }
+  }
+}
+
+impl FromStr for Cron {
+  type Err = CronError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::parse(s)
+  }
+}
+
+/// Why a cron expression failed to parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronError(String);
+
+impl fmt::Display for CronError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid cron expression: {}", self.0)
+  }
+}
+
+impl std::error::Error for CronError {}
+
+fn has(mask: u64, value: u32) -> bool {
+  mask >> value & 1 == 1
+}
+
+fn is_any(field: &str) -> bool {
+  field == "*" || field == "?"
+}
+
+/// Parse one field into a bit mask over `min..=max`.
+fn field(src: &str, name: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, CronError> {
+  let value = |s: &str| -> Result<u32, CronError> {
+    let v = match names.iter().position(|n| n.eq_ignore_ascii_case(s)) {
+      Some(i) => i as u32 + min,
+      None => s
+        .parse()
+        .map_err(|_| CronError(format!("{name}: `{s}` is not a number")))?,
+    };
+
+    match v {
+      v if (min..=max).contains(&v) => Ok(v),
+      v => Err(CronError(format!("{name}: {v} is outside {min}-{max}"))),
+    }
+  };
+
+  let mut mask = 0;
+  for part in src.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => match step.parse::<u32>() {
+        Ok(step) if step > 0 => (range, Some(step)),
+        _ => return Err(CronError(format!("{name}: invalid step `{step}`"))),
+      },
+      None => (part, None),
+    };
+
+    let (lo, hi) = match range.split_once('-') {
+      _ if is_any(range) => (min, max),
+      Some((lo, hi)) => (value(lo)?, value(hi)?),
+      None => {
+        let v = value(range)?;
+        (v, if step.is_some() { max } else { v })
+      }
+    };
+
+    if lo > hi {
+      return Err(CronError(format!("{name}: empty range `{range}`")));
+    }
+
+    for v in (lo..=hi).step_by(step.unwrap_or(1) as usize) {
+      mask |= 1 << v;
+    }
+  }
+
+  Ok(mask)
+}
+
+/// First existing local time after `t`, which falls in a DST gap.
+fn gap_end<Tz: TimeZone>(tz: &Tz, t: NaiveDateTime) -> Option<DateTime<Tz>> {
+  let minute = t.with_second(0)?;
+  (1..=24 * 60)
+    .map(|m| minute + TimeDelta::minutes(m))
+    .find_map(|t| tz.from_local_datetime(&t).earliest())
+}
+
+fn wait(from: DateTime<chrono::Local>, to: DateTime<chrono::Local>) -> PlanWait {
+  let fire = (to - from).to_std().unwrap_or(Duration::ZERO);
+  PlanWait {
+    wake: fire.min(MAX_WAIT),
+    fire,
+  }
+}
+
+impl Timer {
+  /// Run `f` at every occurrence of `schedule` in local time.
+  ///
+  /// The next occurrence is recomputed from the wall clock after each
+  /// run; see [`Cron`] for DST and clock jump handling. The handle's
+  /// `fire_count` counts runs, not the intermediate wake-ups, and
+  /// `remaining` is the time to the next run. `reschedule` does not
+  /// apply. On a virtual-clock timer the wall clock advances with
+  /// [`advance`](Timer::advance).
+  pub fn cron<F>(&self, schedule: Cron, f: F) -> Result<TimerHandle, TimerShutdown>
+  where
+    F: FnMut() + Send + 'static,
+  {
+    let wall = self.wall_clock();
+    let now = wall.now();
+    // `Cron::parse` rejects expressions that never match.
+    let mut next = schedule.next_after(&now);
+    debug_assert!(next.is_some(), "cron schedule never matches");
+    let first = next.map_or(
+      PlanWait {
+        wake: Duration::ZERO,
+        fire: Duration::ZERO,
+      },
+      |t| wait(now, t),
+    );
+
+    let plan = move || {
+      let now = wall.now();
+      match next {
+        None => (false, None),
+        // Woke early: the clock jumped back, or the wait was capped.
+        Some(t) if now < t => (false, Some(wait(now, t))),
+        Some(_) => {
+          next = schedule.next_after(&now);
+          (true, next.map(|t| wait(now, t)))
+        }
+      }
+    };
+
+    self.planned(first, plan, f)
+  }
+}
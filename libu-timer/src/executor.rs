@@ -0,0 +1,87 @@
+use std::thread;
+use std::thread::JoinHandle;
+
+use flume::Sender;
+
+type TimerJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs due timer callbacks off the wheel thread.
+///
+/// Set one with `TimerConfig::executor`. The wheel thread then only
+/// hands jobs over, so a slow callback no longer delays later ticks.
+/// Jobs never unwind: callback panics are caught inside them.
+///
+/// Any `Fn(Box<dyn FnOnce() + Send>)` closure is an executor, which
+/// makes it easy to forward jobs to an existing pool.
+pub trait TimerExecutor: Send + Sync {
+  fn execute(&self, job: TimerJob);
+}
+
+impl<F> TimerExecutor for F
+where
+  F: Fn(TimerJob) + Send + Sync,
+{
+  fn execute(&self, job: TimerJob) {
+    self(job)
+  }
+}
+
+/// Fixed-size thread pool for timer callbacks.
+///
+/// Threads are named `libu-timer-pool-N`. Dropping the pool lets queued
+/// jobs finish, then joins every thread.
+pub struct TimerPool {
+  /// `None` once dropping, so workers see the queue disconnect.
+  tx: Option<Sender<TimerJob>>,
+  workers: Vec<JoinHandle<()>>,
+}
+
+impl TimerPool {
+  /// Spawn `threads` workers (at least one).
+  ///
+  /// # Panics
+  ///
+  /// Panics if a worker thread cannot be spawned.
+  pub fn new(threads: usize) -> Self {
+    let (tx, rx) = flume::unbounded::<TimerJob>();
+
+    let workers = (0..threads.max(1))
+      .map(|i| {
+        let rx = rx.clone();
+        thread::Builder::new()
+          .name(format!("libu-timer-pool-{i}"))
+          .spawn(move || rx.iter().for_each(|job| job()))
+          .expect("failed to spawn timer pool thread")
+      })
+      .collect();
+
+    Self {
+      tx: Some(tx),
+      workers,
+    }
+  }
+}
+
+impl TimerExecutor for TimerPool {
+  fn execute(&self, job: TimerJob) {
+    if let Some(tx) = &self.tx {
+      // Workers only exit once `tx` is dropped, so this cannot fail.
+      let _ = tx.send(job);
+    }
+  }
+}
+
+impl Drop for TimerPool {
+  fn drop(&mut self) {
+    self.tx.take();
+
+    // A job may hold the last reference to the pool; never join the
+    // thread we are running on.
+    let current = thread::current().id();
+    for worker in self.workers.drain(..) {
+      if worker.thread().id() != current {
+        let _ = worker.join();
+      }
+    }
+  }
+}
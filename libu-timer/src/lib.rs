@@ -2,6 +2,20 @@
 #![allow(non_snake_case)]
 #![feature(proc_macro_hygiene)]
 
+mod config;
+mod cron;
+mod executor;
+mod recv;
+mod retry;
+mod sleep;
+mod stats;
 mod timer;
 
+pub use config::*;
+pub use cron::*;
+pub use executor::*;
+pub use recv::*;
+pub use retry::*;
+pub use sleep::*;
+pub use stats::*;
 pub use timer::*;
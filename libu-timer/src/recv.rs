@@ -0,0 +1,139 @@
+//! Timer fires delivered on a channel, so they can be waited on
+//! together with messages.
+
+use std::time::Duration;
+
+use flume::{Receiver, RecvError, Sender, bounded};
+use libu_derive::*;
+use libu_point::*;
+
+use crate::timer::*;
+
+/// A timer task whose fires arrive on a channel, from [`Timer::after`]
+/// or [`Timer::every`].
+///
+/// Each fire sends the task's fire count so far. Fires that happen while
+/// a previous one is still unreceived are coalesced into it, so a slow
+/// receiver sees the latest count rather than a backlog. The channel
+/// disconnects once the task is removed, by [`TimerHandle::remove`],
+/// shutdown or the `Timer` going away; dropping the `TimerRecv` cancels
+/// the task.
+///
+/// [`rx`](Self::rx) is a plain flume receiver, so an event loop can
+/// wait on ticks and messages at once with `select!`.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use libu_timer::Timer;
+///
+/// let timer = Timer::new_virtual();
+/// let tick = timer.every(Duration::from_millis(100)).unwrap();
+///
+/// timer.advance(Duration::from_millis(100));
+/// assert_eq!(tick.try_recv(), Some(1));
+///
+/// // Unreceived fires coalesce.
+/// timer.advance(Duration::from_millis(200));
+/// assert_eq!(tick.try_recv(), Some(3));
+/// assert_eq!(tick.try_recv(), None);
+///
+/// // Removing the task disconnects the channel.
+/// tick.handle().remove();
+/// assert!(tick.recv().is_err());
+/// ```
+pub struct TimerRecv {
+  handle: TimerHandle,
+  rx: Receiver<u64>,
+}
+
+impl TimerRecv {
+  /// Block until the next fire and return the fire count. Fails once
+  /// the task is removed and every fire has been received.
+  pub fn recv(&self) -> Result<u64, RecvError> {
+    self.rx.recv()
+  }
+
+  pub fn try_recv(&self) -> Option<u64> {
+    self.rx.try_recv().ok()
+  }
+
+  pub fn recv_timeout(&self, timeout: Duration) -> Option<u64> {
+    self.rx.recv_timeout(timeout).ok()
+  }
+
+  /// The channel fires are sent on, for `select!`.
+  pub fn rx(&self) -> &Receiver<u64> {
+    &self.rx
+  }
+
+  /// The wheel task backing this channel, to stop, reset or reschedule
+  /// it.
+  pub fn handle(&self) -> &TimerHandle {
+    &self.handle
+  }
+}
+
+impl AsRef<Receiver<u64>> for TimerRecv {
+  fn as_ref(&self) -> &Receiver<u64> {
+    &self.rx
+  }
+}
+
+impl Drop for TimerRecv {
+  fn drop(&mut self) {
+    self.handle.remove();
+  }
+}
+
+impl std::fmt::Debug for TimerRecv {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("TimerRecv")
+      .field("handle", &self.handle)
+      .finish()
+  }
+}
+
+impl Timer {
+  /// Channel that receives once, after `delay`.
+  pub fn after(&self, delay: Duration) -> Result<TimerRecv, TimerShutdown> {
+    let (tx, rx) = bounded(1);
+    let tx = Some(tx).iMrc();
+
+    #[clone(tx)]
+    let handle = self.delay(delay, fire_into(tx, rx.clone()))?;
+    handle.on_remove(move || tx.with_mut(|tx| *tx = None));
+
+    Ok(TimerRecv { handle, rx })
+  }
+
+  /// Channel that receives every `period`.
+  pub fn every(&self, period: Duration) -> Result<TimerRecv, TimerShutdown> {
+    let (tx, rx) = bounded(1);
+    let tx = Some(tx).iMrc();
+
+    #[clone(tx)]
+    let handle = self.ticker(period, fire_into(tx, rx.clone()))?;
+    handle.on_remove(move || tx.with_mut(|tx| *tx = None));
+
+    Ok(TimerRecv { handle, rx })
+  }
+}
+
+/// Send each fire's count on `tx`, until the task's `on_remove` hook
+/// takes the sender and so disconnects the channel.
+fn fire_into(tx: Mrc<Option<Sender<u64>>>, rx: Receiver<u64>) -> impl FnMut() + Send + 'static {
+  let mut fired = 0;
+
+  move || {
+    fired += 1;
+    tx.with(|tx| {
+      let Some(tx) = tx else { return };
+      // Full: replace the unreceived count with the latest one.
+      while tx.try_send(fired).is_err() {
+        let _ = rx.try_recv();
+      }
+    });
+  }
+}
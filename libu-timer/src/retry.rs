@@ -0,0 +1,339 @@
+use std::fmt;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use libu_derive::*;
+
+use crate::timer::*;
+
+/// When to retry a failed attempt, and how many attempts to make.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use libu_timer::{Backoff, Timer};
+///
+/// let timer = Timer::new_virtual();
+///
+/// let mut tries = 0;
+/// let handle = timer.retry(
+///   Backoff::exponential(Duration::from_millis(100), Duration::from_secs(1)).attempts(5),
+///   move || {
+///     tries += 1;
+///     if tries < 3 { Err("busy") } else { Ok(tries) }
+///   },
+/// )
+/// .unwrap();
+///
+/// // Attempts at 100ms, 200ms and 400ms.
+/// timer.advance(Duration::from_millis(400));
+/// assert!(handle.is_finished());
+/// assert_eq!(handle.wait(), Ok(3));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Backoff {
+  kind: BackoffKind,
+  /// Total attempts, `None` for unlimited.
+  attempts: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+enum BackoffKind {
+  Exponential { base: Duration, max: Duration },
+  Decorrelated { base: Duration, max: Duration },
+  Fixed { period: Duration, jitter: Duration },
+}
+
+impl Backoff {
+  /// Wait `base`, then twice as long after each failure, never more
+  /// than `max`.
+  pub fn exponential(base: Duration, max: Duration) -> Self {
+    Self::new(BackoffKind::Exponential { base, max })
+  }
+
+  /// "Decorrelated jitter": wait a random time between `base` and three
+  /// times the previous wait, never more than `max`. Spreads out clients
+  /// that failed together better than plain exponential backoff.
+  pub fn decorrelated(base: Duration, max: Duration) -> Self {
+    Self::new(BackoffKind::Decorrelated { base, max })
+  }
+
+  /// Wait `period`, give or take up to `jitter`, between attempts.
+  pub fn fixed(period: Duration, jitter: Duration) -> Self {
+    Self::new(BackoffKind::Fixed { period, jitter })
+  }
+
+  /// Give up after `attempts` attempts in total, counting the first
+  /// one. Unlimited by default; zero is treated as one.
+  pub fn attempts(mut self, attempts: u32) -> Self {
+    self.attempts = Some(attempts.max(1));
+    self
+  }
+
+  fn new(kind: BackoffKind) -> Self {
+    Self {
+      kind,
+      attempts: None,
+    }
+  }
+
+  fn exhausted(&self, attempts: u32) -> bool {
+    self.attempts.is_some_and(|max| attempts >= max)
+  }
+
+  /// Wait before the attempt following failed attempt number `attempt`
+  /// (1-based), given the previous wait.
+  fn delay(&self, attempt: u32, prev: Duration, rng: &mut Rng) -> Duration {
+    match self.kind {
+      BackoffKind::Exponential { base, max } => 2u32
+        .checked_pow(attempt - 1)
+        .map_or(max, |m| base.saturating_mul(m))
+        .min(max),
+      BackoffKind::Decorrelated { base, max } => {
+        let hi = prev.saturating_mul(3).max(base);
+        rng.between(base, hi).min(max)
+      }
+      BackoffKind::Fixed { period, jitter } => {
+        rng.between(period.saturating_sub(jitter), period.saturating_add(jitter))
+      }
+    }
+  }
+}
+
+/// Why a retried operation did not succeed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryError<E> {
+  /// Every attempt failed; `error` is the last attempt's.
+  Exhausted { attempts: u32, error: E },
+  /// [`RetryHandle::cancel`] was called first, or the timer shut down
+  /// or was dropped.
+  Cancelled,
+  /// An attempt panicked. The panic is reported like any other timer
+  /// callback panic.
+  Panicked,
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Exhausted { attempts, error } => {
+        write!(f, "gave up after {attempts} attempts: {error}")
+      }
+      Self::Cancelled => write!(f, "retry cancelled"),
+      Self::Panicked => write!(f, "retry attempt panicked"),
+    }
+  }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RetryError<E> {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Exhausted { error, .. } => Some(error),
+      _ => None,
+    }
+  }
+}
+
+type RetryOutcome<T, E> = Result<T, RetryError<E>>;
+
+struct RetryState<T, E> {
+  attempts: u32,
+  /// Set once, by whichever of success, exhaustion, panic or cancel
+  /// comes first.
+  outcome: Option<RetryOutcome<T, E>>,
+}
+
+struct RetryShared<T, E> {
+  state: Mutex<RetryState<T, E>>,
+  done: Condvar,
+}
+
+impl<T, E> RetryShared<T, E> {
+  fn lock(&self) -> std::sync::MutexGuard<'_, RetryState<T, E>> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  /// Count a new attempt, or `None` if the retry already finished.
+  fn begin(&self) -> Option<u32> {
+    let mut state = self.lock();
+    if state.outcome.is_some() {
+      return None;
+    }
+
+    state.attempts += 1;
+    Some(state.attempts)
+  }
+
+  fn finish(&self, outcome: RetryOutcome<T, E>) {
+    let mut state = self.lock();
+    if state.outcome.is_none() {
+      state.outcome = Some(outcome);
+      self.done.notify_all();
+    }
+  }
+}
+
+/// A running retry loop, from [`Timer::retry`].
+///
+/// Dropping the handle does not cancel the loop. If the timer shuts
+/// down or is dropped, the outcome is [`RetryError::Cancelled`] as soon
+/// as the loop's task is removed, or once an attempt that is already
+/// running has failed.
+pub struct RetryHandle<T, E> {
+  handle: TimerHandle,
+  shared: Arc<RetryShared<T, E>>,
+}
+
+impl<T, E> RetryHandle<T, E> {
+  /// Attempts started so far.
+  pub fn attempts(&self) -> u32 {
+    self.shared.lock().attempts
+  }
+
+  /// Returns `true` once the outcome is known.
+  pub fn is_finished(&self) -> bool {
+    self.shared.lock().outcome.is_some()
+  }
+
+  /// Stop retrying. An attempt already running completes, but its
+  /// result is discarded and the outcome is [`RetryError::Cancelled`].
+  pub fn cancel(&self) {
+    self.handle.remove();
+    self.shared.finish(Err(RetryError::Cancelled));
+  }
+
+  /// Block until the loop finishes and return its outcome.
+  ///
+  /// On a virtual clock, only call this once [`is_finished`](Self::is_finished)
+  /// returns `true`, or from a thread other than the one calling
+  /// `advance`.
+  pub fn wait(self) -> RetryOutcome<T, E> {
+    let mut state = self.shared.lock();
+    loop {
+      if let Some(outcome) = state.outcome.take() {
+        return outcome;
+      }
+      state = self
+        .shared
+        .done
+        .wait(state)
+        .unwrap_or_else(|e| e.into_inner());
+    }
+  }
+
+  /// Like [`wait`](Self::wait), but give the handle back if the loop is
+  /// still running after `timeout`.
+  pub fn wait_timeout(self, timeout: Duration) -> Result<RetryOutcome<T, E>, Self> {
+    let deadline = Instant::now() + timeout;
+    let mut state = self.shared.lock();
+    loop {
+      if let Some(outcome) = state.outcome.take() {
+        return Ok(outcome);
+      }
+
+      let now = Instant::now();
+      if now >= deadline {
+        drop(state);
+        return Err(self);
+      }
+
+      state = self
+        .shared
+        .done
+        .wait_timeout(state, deadline - now)
+        .unwrap_or_else(|e| e.into_inner())
+        .0;
+    }
+  }
+}
+
+/// xorshift64*, seeded per retry loop from std's randomly keyed hasher.
+/// Jitter only needs to decorrelate clients, not be unpredictable.
+struct Rng(u64);
+
+impl Rng {
+  fn new() -> Self {
+    Self(RandomState::new().build_hasher().finish() | 1)
+  }
+
+  /// Uniform in `[0, 1)`.
+  fn unit(&mut self) -> f64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+  }
+
+  fn between(&mut self, lo: Duration, hi: Duration) -> Duration {
+    lo + hi.saturating_sub(lo).mul_f64(self.unit())
+  }
+}
+
+impl Timer {
+  /// Call `f` until it returns `Ok` or `backoff` runs out of attempts,
+  /// waiting between failures as `backoff` dictates. The first attempt
+  /// runs on the next tick.
+  ///
+  /// Attempts run like any other callback, on the wheel thread or the
+  /// configured executor, and never overlap.
+  pub fn retry<F, T, E>(
+    &self,
+    backoff: Backoff,
+    mut f: F,
+  ) -> Result<RetryHandle<T, E>, TimerShutdown>
+  where
+    F: FnMut() -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+  {
+    let shared = Arc::new(RetryShared {
+      state: Mutex::new(RetryState {
+        attempts: 0,
+        outcome: None,
+      }),
+      done: Condvar::new(),
+    });
+
+    let mut rng = Rng::new();
+    let mut prev = Duration::ZERO;
+
+    #[clone(shared)]
+    let handle = self.delay_with(Duration::ZERO, move |handle| {
+      let Some(attempt) = shared.begin() else {
+        return;
+      };
+
+      let result = match catch_unwind(AssertUnwindSafe(&mut f)) {
+        Ok(result) => result,
+        Err(panic) => {
+          shared.finish(Err(RetryError::Panicked));
+          resume_unwind(panic);
+        }
+      };
+
+      match result {
+        Ok(value) => shared.finish(Ok(value)),
+        Err(error) if backoff.exhausted(attempt) => shared.finish(Err(RetryError::Exhausted {
+          attempts: attempt,
+          error,
+        })),
+        Err(_) => {
+          prev = backoff.delay(attempt, prev, &mut rng);
+          handle.reschedule(prev);
+
+          // Not re-armed: the timer is shutting down.
+          if handle.remaining().is_none() {
+            shared.finish(Err(RetryError::Cancelled));
+          }
+        }
+      }
+    })?;
+    #[clone(shared)]
+    handle.on_remove(move || shared.finish(Err(RetryError::Cancelled)));
+
+    Ok(RetryHandle { handle, shared })
+  }
+}
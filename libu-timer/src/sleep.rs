@@ -0,0 +1,173 @@
+//! Futures and streams driven by the timer's worker thread.
+//!
+//! Each [`Sleep`] or [`Interval`] owns an ordinary wheel task whose
+//! callback records the fire and wakes the stored [`Waker`], so no
+//! async runtime timer is involved. Dropping either one removes its
+//! task, exactly like [`TimerHandle::remove`]. If the task is removed
+//! first, by shutdown or by dropping the last `Timer` clone, the future
+//! resolves to [`TimerShutdown`] and the stream ends.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+use libu_derive::*;
+use libu_point::*;
+
+use crate::timer::*;
+
+#[derive(Default)]
+struct WakeState {
+  /// Fires not yet observed by `poll`.
+  fired: u64,
+  /// The task was removed; no more fires will come.
+  removed: bool,
+  waker: Option<Waker>,
+}
+
+impl WakeState {
+  fn fire(state: &Mrc<WakeState>) {
+    Self::wake(state, |x| x.fired += 1);
+  }
+
+  fn remove(state: &Mrc<WakeState>) {
+    Self::wake(state, |x| x.removed = true);
+  }
+
+  fn wake(state: &Mrc<WakeState>, update: impl FnOnce(&mut WakeState)) {
+    // Wake outside the lock, the waker may poll inline.
+    let waker = state.with_mut(|x| {
+      update(x);
+      x.waker.take()
+    });
+
+    if let Some(waker) = waker {
+      waker.wake();
+    }
+  }
+
+  /// Consume one fire, or park `cx`'s waker until the next one. Fires
+  /// that came in before the task was removed are still delivered.
+  fn poll(state: &Mrc<WakeState>, cx: &mut Context<'_>) -> Poll<Result<(), TimerShutdown>> {
+    state.with_mut(|x| {
+      if x.fired > 0 {
+        x.fired -= 1;
+        return Poll::Ready(Ok(()));
+      }
+      if x.removed {
+        return Poll::Ready(Err(TimerShutdown));
+      }
+
+      match &mut x.waker {
+        Some(waker) => waker.clone_from(cx.waker()),
+        None => x.waker = Some(cx.waker().clone()),
+      }
+      Poll::Pending
+    })
+  }
+}
+
+/// Future returned by [`Timer::sleep`] and [`Timer::sleep_until`].
+///
+/// Completes with `Ok(())` once the underlying one-shot task fires, or
+/// with `Err(TimerShutdown)` if the task is removed first: the timer
+/// shut down or was dropped, or [`TimerHandle::remove`] was called.
+/// Dropping it before then cancels the task.
+pub struct Sleep {
+  handle: TimerHandle,
+  state: Mrc<WakeState>,
+}
+
+impl Sleep {
+  /// The wheel task backing this future.
+  pub fn handle(&self) -> &TimerHandle {
+    &self.handle
+  }
+}
+
+impl Future for Sleep {
+  type Output = Result<(), TimerShutdown>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    WakeState::poll(&self.state, cx)
+  }
+}
+
+impl Drop for Sleep {
+  fn drop(&mut self) {
+    self.handle.remove();
+  }
+}
+
+/// Stream returned by [`Timer::interval`].
+///
+/// Yields `()` once per period. Periods that elapse while the stream is
+/// not polled are queued and yielded back to back. The stream ends
+/// once the ticker is removed, by shutdown, by dropping the timer or by
+/// [`TimerHandle::remove`]; dropping the stream cancels the ticker.
+pub struct Interval {
+  handle: TimerHandle,
+  state: Mrc<WakeState>,
+}
+
+impl Interval {
+  /// Wait for the next period. Fails once the ticker is removed.
+  pub async fn tick(&mut self) -> Result<(), TimerShutdown> {
+    std::future::poll_fn(|cx| WakeState::poll(&self.state, cx)).await
+  }
+
+  /// The wheel task backing this stream.
+  pub fn handle(&self) -> &TimerHandle {
+    &self.handle
+  }
+}
+
+impl Stream for Interval {
+  type Item = ();
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+    WakeState::poll(&self.state, cx).map(Result::ok)
+  }
+}
+
+impl Drop for Interval {
+  fn drop(&mut self) {
+    self.handle.remove();
+  }
+}
+
+impl Timer {
+  /// Future that completes after `delay`, rounded up to at least one
+  /// tick.
+  pub fn sleep(&self, delay: Duration) -> Result<Sleep, TimerShutdown> {
+    let state = WakeState::default().iMrc();
+
+    #[clone(state)]
+    let handle = self.delay(delay, move || WakeState::fire(&state))?;
+    #[clone(state)]
+    handle.on_remove(move || WakeState::remove(&state));
+
+    Ok(Sleep { handle, state })
+  }
+
+  /// Future that completes at `deadline`, measured against
+  /// [`Timer::now`]. A deadline in the past still waits for the next
+  /// tick.
+  pub fn sleep_until(&self, deadline: Instant) -> Result<Sleep, TimerShutdown> {
+    self.sleep(deadline.saturating_duration_since(self.now()))
+  }
+
+  /// Stream that yields every `period`, starting one period from now.
+  pub fn interval(&self, period: Duration) -> Result<Interval, TimerShutdown> {
+    let state = WakeState::default().iMrc();
+
+    #[clone(state)]
+    let handle = self.ticker(period, move || WakeState::fire(&state))?;
+    #[clone(state)]
+    handle.on_remove(move || WakeState::remove(&state));
+
+    Ok(Interval { handle, state })
+  }
+}
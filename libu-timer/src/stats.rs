@@ -0,0 +1,108 @@
+use std::any::Any;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use libu_trait::PanicMsg;
+
+/// Receives the message of every panicking timer callback. Set one
+/// with `TimerConfig::panic_hook`; without it panics are logged through
+/// `libu-log` at error level.
+pub type TimerPanicHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Upper bounds of the lateness histogram buckets: 0, then 1ms to
+/// 1024ms in powers of two, then everything above.
+const LATENESS_BOUNDS: usize = 13;
+
+/// Point-in-time view of a [`Timer`](crate::Timer), from
+/// [`Timer::stats`](crate::Timer::stats).
+///
+/// Counters are read without stopping the wheel, so a snapshot taken
+/// while callbacks are running may be off by the tasks in flight.
+#[derive(Clone, Debug, Default)]
+pub struct TimerStats {
+  /// Tasks waiting in the wheel, including stopped tickers. Tasks whose
+  /// callback is running or queued on the executor are not counted.
+  pub tasks: usize,
+  /// Waiting tasks per bucket, indexed `[level][bucket]`.
+  pub buckets: Vec<Vec<usize>>,
+  /// Callbacks run, including the ones that panicked.
+  pub fired: u64,
+  /// Callbacks that panicked. Their task is removed.
+  pub panicked: u64,
+  /// How late the worker thread woke for each tick, compared to the
+  /// tick's deadline, as `(upper bound, count)` pairs. The last bound is
+  /// `Duration::MAX`. Always empty for a virtual clock, which has no
+  /// worker.
+  pub lateness: Vec<(Duration, u64)>,
+}
+
+/// Counters shared between the wheel and the jobs it hands to the
+/// executor.
+pub(crate) struct TimerCounters {
+  fired: AtomicU64,
+  panicked: AtomicU64,
+  lateness: [AtomicU64; LATENESS_BOUNDS],
+  panic_hook: Option<TimerPanicHook>,
+}
+
+impl TimerCounters {
+  pub(crate) fn new(panic_hook: Option<TimerPanicHook>) -> Self {
+    Self {
+      fired: AtomicU64::new(0),
+      panicked: AtomicU64::new(0),
+      lateness: Default::default(),
+      panic_hook,
+    }
+  }
+
+  fn bound(i: usize) -> Duration {
+    match i {
+      0 => Duration::ZERO,
+      i if i < LATENESS_BOUNDS - 1 => Duration::from_millis(1 << (i - 1)),
+      _ => Duration::MAX,
+    }
+  }
+
+  pub(crate) fn record_lateness(&self, late: Duration) {
+    let i = (0..LATENESS_BOUNDS)
+      .find(|&i| late <= Self::bound(i))
+      .unwrap_or(LATENESS_BOUNDS - 1);
+    self.lateness[i].fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Count one callback run, reporting its panic if it had one.
+  pub(crate) fn record_fire(&self, result: &Result<(), Box<dyn Any + Send>>) {
+    self.fired.fetch_add(1, Ordering::Relaxed);
+
+    let Err(payload) = result else {
+      return;
+    };
+    self.panicked.fetch_add(1, Ordering::Relaxed);
+
+    let msg = payload.panic_msg();
+
+    match &self.panic_hook {
+      Some(hook) => hook(msg),
+      None => libu_log::error!("timer callback panicked: {msg}"),
+    }
+  }
+
+  /// Snapshot with the counter fields filled in; the wheel adds the
+  /// occupancy.
+  pub(crate) fn snapshot(&self, virtual_clock: bool) -> TimerStats {
+    let lateness = match virtual_clock {
+      true => Vec::new(),
+      false => (0..LATENESS_BOUNDS)
+        .map(|i| (Self::bound(i), self.lateness[i].load(Ordering::Relaxed)))
+        .collect(),
+    };
+
+    TimerStats {
+      fired: self.fired.load(Ordering::Relaxed),
+      panicked: self.panicked.load(Ordering::Relaxed),
+      lateness,
+      ..Default::default()
+    }
+  }
+}
@@ -1,109 +1,341 @@
-//! Single-level timing wheel with a 100ms tick.
+//! Hierarchical timing wheel with a configurable tick.
 //!
 //! # Resolution and range
 //!
-//! - Tick resolution: 100ms. Durations are rounded up to the next tick.
-//! - Wheel size: 4096 buckets, so a task targeting `tick + N` lands in
-//!   `buckets[(tick + N) % 4096]`.
-//! - There is no upper bound on `Duration`. Tasks with delays larger
-//!   than one wheel rotation (~409 seconds) still fire correctly: each
-//!   task carries its absolute target tick, and dispatch only fires
-//!   tasks whose target tick matches the current tick exactly.
+//! - Tick resolution: 100ms by default, configurable down to 1ms via
+//!   [`TimerConfig`]. Durations are rounded up to the next tick.
+//! - Each level has `wheel_size` buckets (64 by default). A bucket on
+//!   level 0 spans one tick, a bucket on level `n` spans `64^n` ticks.
+//!   There are enough levels to cover the whole `u64` tick range (eleven
+//!   with 64 buckets), so there is no upper bound on `Duration`.
+//! - A task is placed on the lowest level whose range covers its
+//!   remaining delay: a task due in 30 ticks sits in level 0, a task
+//!   due in one hour (36000 ticks) sits in level 2.
+//!
+//! # Cascading
+//!
+//! Each time the current tick crosses a level-`n` bucket boundary, that
+//! bucket is emptied and its tasks are re-placed relative to the current
+//! tick, which always moves them to a lower level. By the time a task
+//! reaches level 0 its bucket only holds tasks due on that exact tick.
 //!
 //! # Performance characteristics
 //!
 //! - Registration is O(1) plus a per-bucket mutex acquisition.
-//! - Dispatch scans only the current tick's bucket, not the whole wheel.
-//! - However, long-delay tasks remain pinned to one bucket for their
-//!   entire lifetime. Each wheel rotation (~409s) the dispatcher walks
-//!   that bucket and checks every task. Workloads with very large
-//!   numbers of long-delay tasks (e.g. hundreds of thousands of tasks
-//!   each ~hours out) will see this scan cost grow.
+//! - Dispatch takes only the current tick's level-0 bucket; every task
+//!   in it is due.
+//! - A task cascades at most once per level, so the total work per task
+//!   is bounded by the number of levels regardless of its delay. Long-delay
+//!   tasks are never rescanned while they wait.
+//!
+//! # Virtual clock
+//!
+//! A timer built with `TimerConfig::virtual_clock` (or
+//! [`Timer::new_virtual`]) has no worker thread. Time only moves when
+//! [`Timer::advance`] is called, which dispatches every elapsed tick
+//! synchronously, so timer-driven code can be tested deterministically.
+//! Components should take a `Timer` rather than calling the global
+//! [`delay`]/[`ticker`] functions to be testable this way.
+//!
+//! # Calendar schedules
+//!
+//! [`Timer::cron`] runs a callback on a [`Cron`] expression. Wall-clock
+//! time is not monotonic, so such tasks re-check the local time each
+//! time they wake and sleep at most a minute at a time. On a virtual
+//! clock, local time starts at the wall time the timer was built and
+//! moves with `advance`.
 //!
-//!   A hierarchical timing wheel would amortize this by cascading
-//!   long-delay tasks down through coarser levels. It is not
-//!   implemented here.
+//! # Callback dispatch
+//!
+//! By default due callbacks run inline on the worker thread, so a slow
+//! callback delays every later tick. With `TimerConfig::executor` the
+//! worker only hands them to a [`TimerExecutor`] such as [`TimerPool`]
+//! and keeps ticking on schedule. Fires of the same task are still run
+//! one at a time and in order. A virtual clock ignores the executor and
+//! keeps running callbacks inside `advance`.
+//!
+//! A panicking callback is caught, its task removed, and the panic
+//! message passed to `TimerConfig::panic_hook`, or logged through
+//! `libu-log` when no hook is set. [`Timer::stats`] counts fires and
+//! panics.
+//!
+//! # Shutdown
+//!
+//! [`Timer::shutdown_with`] stops a timer under a [`ShutdownPolicy`]
+//! and returns the tasks that will never fire. Registering on a timer
+//! that has been shut down fails with [`TimerShutdown`].
 //!
 //! # Concurrency
 //!
 //! - `tick` is an `AtomicU64` written only by the worker thread.
-//! - Each of the 4096 buckets has its own `Mutex`. Registration and
+//! - Each bucket has its own `Mutex`. Registration, cascading and
 //!   dispatch only contend when they target the same bucket.
-//! - Callbacks run with no bucket lock held, so a slow callback does
-//!   not block concurrent registrations.
+//! - Callbacks run with no bucket or task lock held, so a slow callback
+//!   does not block concurrent registrations, and a callback may use
+//!   its own `TimerHandle`.
 
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::Weak;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
 use std::time::Instant;
 
+use chrono::{DateTime, Local, TimeDelta};
+
 use libu_derive::*;
 use libu_point::*;
 
-const WHEEL_SIZE: usize = 4096;
-const TIMER: std::sync::LazyLock<Timer> = std::sync::LazyLock::new(|| Timer::new());
+use crate::config::*;
+use crate::cron::*;
+use crate::executor::*;
+use crate::recv::*;
+use crate::retry::*;
+use crate::sleep::*;
+use crate::stats::*;
+
+static TIMER: std::sync::LazyLock<Timer> = std::sync::LazyLock::new(Timer::new);
+
+/// The global timer is private, so nothing can shut it down.
+const GLOBAL_TIMER: &str = "the global timer is never shut down";
 
 pub fn delay<F>(delay: Duration, f: F) -> TimerHandle
 where
   F: FnMut() + Send + 'static,
 {
-  TIMER.delay(delay, f)
+  TIMER.delay(delay, f).expect(GLOBAL_TIMER)
 }
 
 pub fn ticker<F>(repeat: Duration, f: F) -> TimerHandle
 where
   F: FnMut() + Send + 'static,
 {
-  TIMER.ticker(repeat, f)
+  TIMER.ticker(repeat, f).expect(GLOBAL_TIMER)
+}
+
+pub fn sleep(delay: Duration) -> Sleep {
+  TIMER.sleep(delay).expect(GLOBAL_TIMER)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+  TIMER.sleep_until(deadline).expect(GLOBAL_TIMER)
+}
+
+pub fn interval(period: Duration) -> Interval {
+  TIMER.interval(period).expect(GLOBAL_TIMER)
+}
+
+pub fn after(delay: Duration) -> TimerRecv {
+  TIMER.after(delay).expect(GLOBAL_TIMER)
+}
+
+pub fn every(period: Duration) -> TimerRecv {
+  TIMER.every(period).expect(GLOBAL_TIMER)
+}
+
+pub fn cron<F>(schedule: Cron, f: F) -> TimerHandle
+where
+  F: FnMut() + Send + 'static,
+{
+  TIMER.cron(schedule, f).expect(GLOBAL_TIMER)
+}
+
+pub fn retry<F, T, E>(backoff: Backoff, f: F) -> RetryHandle<T, E>
+where
+  F: FnMut() -> Result<T, E> + Send + 'static,
+  T: Send + 'static,
+  E: Send + 'static,
+{
+  TIMER.retry(backoff, f).expect(GLOBAL_TIMER)
 }
 
-type TimerTaskCallback = Box<dyn FnMut() + Send + 'static>;
+/// Locked separately from the task so a running callback never blocks
+/// the wheel, or its own handle, from updating the task's state.
+type TimerTaskCallback = Mrc<Box<dyn FnMut() + Send + 'static>>;
+
+/// Consulted each time a planned task comes due. Returns whether this
+/// wake-up is a fire, and when the next wake-up and fire are (`None`
+/// removes the task).
+type TimerPlan = Box<dyn FnMut() -> (bool, Option<PlanWait>) + Send + 'static>;
+
+/// How long a planned task waits, from now.
+pub(crate) struct PlanWait {
+  /// Until the next wake-up, when the plan is consulted again.
+  pub(crate) wake: Duration,
+  /// Until the next fire, at or after the next wake-up.
+  pub(crate) fire: Duration,
+}
+
+/// Run once when a task is removed, to release whoever waits on it.
+type TimerRemoveHook = Box<dyn FnOnce() + Send + 'static>;
 
 struct TimerTask {
   remove: bool,
   run: bool,
   /// Absolute tick at which this task should fire next.
   delay: u64,
-  /// Repeat interval in ticks. `None` means one-shot.
-  repeat: Option<u64>,
+  /// Delay in ticks that `reset` re-arms with: the original delay of a
+  /// one-shot, the repeat interval of a ticker.
+  period: u64,
+  /// Re-arm by `period` after every fire.
+  repeat: bool,
+  /// Calendar-style schedule deciding fires and re-arming itself.
+  plan: Option<TimerPlan>,
+  /// Absolute tick of a planned task's next fire, which may be several
+  /// wake-ups away.
+  fire_at: u64,
+  /// Fires handed to the executor but not yet run.
+  queued: u64,
+  /// Number of times the callback has been dispatched.
+  fired: u64,
+  /// Level, bucket and index in the bucket of the task's entry, `None`
+  /// once it has left the wheel.
+  slot: Option<(usize, usize, usize)>,
+  /// Bumped on every placement and removal. An entry carrying an older
+  /// generation was taken out of its bucket for a cascade or dispatch
+  /// before the task moved, and is skipped.
+  generation: u64,
+  /// Taken and run, outside the task lock, once the task is removed.
+  on_remove: Option<TimerRemoveHook>,
   callback: TimerTaskCallback,
 }
 
 impl TimerTask {
-  fn new<F>(delay: u64, repeat: Option<u64>, f: F) -> Self
+  fn new<F>(period: u64, repeat: bool, f: F) -> Self
   where
     F: FnMut() + Send + 'static,
   {
     Self {
       remove: false,
       run: true,
-      delay,
+      delay: 0,
+      period,
       repeat,
-      callback: f.iBox(),
+      plan: None,
+      fire_at: 0,
+      queued: 0,
+      fired: 0,
+      slot: None,
+      generation: 0,
+      on_remove: None,
+      callback: (f.iBox() as Box<dyn FnMut() + Send>).iMrc(),
     }
   }
 }
 
+impl TimerTask {
+  /// Mark the task removed and hand back its `on_remove` hook, for the
+  /// caller to run once the task lock is released.
+  fn mark_removed(&mut self) -> Option<TimerRemoveHook> {
+    self.run = false;
+    self.remove = true;
+    self.on_remove.take()
+  }
+
+  /// Run the callback, isolating panics so they cannot kill the thread
+  /// running it. A task that panics is removed to avoid repeated panics
+  /// on every fire. Returns `false` if it panicked.
+  fn invoke(task: &Mrc<TimerTask>, callback: &TimerTaskCallback, counters: &TimerCounters) -> bool {
+    let result = callback.with_mut(|f| std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)));
+    counters.record_fire(&result);
+    if result.is_err()
+      && let Some(hook) = task.with_mut(TimerTask::mark_removed)
+    {
+      hook();
+    }
+
+    result.is_ok()
+  }
+}
+
+/// Control handle for a scheduled task. Cloning is cheap and every
+/// clone refers to the same task.
+///
+/// Rescheduling methods take effect immediately: the task is moved to
+/// the bucket of its new deadline. They have no effect on a removed
+/// task or once the owning `Timer` has been dropped.
 #[derive(Clone)]
-pub struct TimerHandle(Mrc<TimerTask>);
+pub struct TimerHandle {
+  task: Mrc<TimerTask>,
+  /// Weak so a callback capturing its own handle does not keep the
+  /// wheel alive.
+  wheel: Weak<TimerWheel>,
+}
 
 impl TimerHandle {
   pub fn start(&self) {
-    self.0.with_mut(|x| x.run = true);
+    self.task.with_mut(|x| x.run = true);
   }
 
   pub fn stop(&self) {
-    self.0.with_mut(|x| x.run = false);
+    self.task.with_mut(|x| x.run = false);
   }
 
+  /// Cancel the task for good and release its bucket entry.
   pub fn remove(&self) {
-    self.0.with_mut(|x| {
-      x.run = false;
-      x.remove = true;
+    let wheel = self.wheel.upgrade();
+    let hook = self.task.with_mut(|x| {
+      if let Some(wheel) = wheel {
+        wheel.unlink(&self.task, x);
+      }
+      x.mark_removed()
     });
+
+    if let Some(hook) = hook {
+      hook();
+    }
+  }
+
+  /// Push the deadline back to one original delay (or, for a ticker,
+  /// one interval) from now. Re-arms a one-shot task that already
+  /// fired, which is what idle timeouts and debouncing need.
+  pub fn reset(&self) {
+    self.rearm(|_, x| x.period);
+  }
+
+  /// Move the next fire to `delay` from now. A ticker continues at its
+  /// usual interval afterwards. Re-arms a one-shot task that already
+  /// fired.
+  ///
+  /// A [`cron`](Timer::cron) task follows its schedule only; this has no
+  /// effect on it, and debug builds assert that it is not one.
+  pub fn reschedule(&self, delay: Duration) {
+    let planned = self.task.with(|x| x.plan.is_some());
+    debug_assert!(!planned, "reschedule has no effect on a cron task");
+    if !planned {
+      self.rearm(|wheel, _| wheel.duration_to_ticks(delay));
+    }
+  }
+
+  /// Change a ticker's interval. The next fire moves to one new
+  /// interval from now. No effect on one-shot or cron tasks.
+  pub fn set_interval(&self, interval: Duration) {
+    self.rearm(|wheel, x| {
+      if x.repeat {
+        x.period = wheel.duration_to_ticks(interval).max(1);
+      }
+      x.period
+    });
+  }
+
+  /// Time until the next fire, rounded to ticks. `None` if the task
+  /// will not fire again: it was removed, or it is a one-shot that has
+  /// already fired.
+  pub fn remaining(&self) -> Option<Duration> {
+    let wheel = self.wheel.upgrade()?;
+    let at = self.task.with(|x| {
+      let at = if x.plan.is_some() { x.fire_at } else { x.delay };
+      (!x.remove && x.slot.is_some()).then_some(at)
+    })?;
+
+    Some(wheel.ticks_to_duration(at.saturating_sub(wheel.origin())))
+  }
+
+  /// Number of times the task has fired while running.
+  pub fn fire_count(&self) -> u64 {
+    self.task.with(|x| x.fired)
   }
 
   /// Returns `true` if the task is currently scheduled to fire.
@@ -111,192 +343,949 @@ impl TimerHandle {
   /// A removed task returns `false`. A stopped (but not removed) ticker
   /// also returns `false`; calling `start()` will resume it.
   pub fn is_running(&self) -> bool {
-    self.0.with(|x| x.run && !x.remove)
+    self.task.with(|x| x.run && !x.remove)
   }
 
   /// Returns `true` once `remove()` has been called or the callback
   /// panicked (panicking tasks are auto-removed).
   pub fn is_removed(&self) -> bool {
-    self.0.with(|x| x.remove)
+    self.task.with(|x| x.remove)
+  }
+
+  /// Run `f` once the task is removed, however that happens: `remove`,
+  /// a panicking callback, shutdown or the last `Timer` clone dropping.
+  /// Runs `f` right away if the task is already removed.
+  pub(crate) fn on_remove<F>(&self, f: F)
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    let f = self.task.with_mut(|x| {
+      if x.remove {
+        return Some(f);
+      }
+      x.on_remove = Some(Box::new(f));
+      None
+    });
+
+    if let Some(f) = f {
+      f();
+    }
+  }
+
+  /// Re-arm the task `ticks(wheel, task)` ticks from now.
+  fn rearm<F>(&self, ticks: F)
+  where
+    F: FnOnce(&TimerWheel, &mut TimerTask) -> u64,
+  {
+    let Some(wheel) = self.wheel.upgrade() else {
+      return;
+    };
+
+    self.task.with_mut(|x| {
+      if !x.remove && !wheel.closed.load(Ordering::Acquire) {
+        let ticks = ticks(&wheel, x);
+        wheel.arm(&self.task, x, ticks);
+      }
+    });
+  }
+}
+
+impl std::fmt::Debug for TimerHandle {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("TimerHandle")
+      .field("running", &self.is_running())
+      .field("remaining", &self.remaining())
+      .field("fired", &self.fire_count())
+      .finish()
+  }
+}
+
+/// Handles are equal when they control the same task.
+impl PartialEq for TimerHandle {
+  fn eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.task, &other.task)
+  }
+}
+
+impl Eq for TimerHandle {}
+
+/// Tasks waiting in one wheel slot, each with the generation it was
+/// placed with.
+///
+/// Removing an entry leaves a hole that the next insert reuses, so
+/// indices held by tasks stay valid and removal is O(1) without
+/// touching any other task.
+#[derive(Default)]
+struct TimerBucket {
+  entries: Vec<Option<(Mrc<TimerTask>, u64)>>,
+  /// Holes in `entries`.
+  free: Vec<usize>,
+}
+
+impl TimerBucket {
+  fn insert(&mut self, task: Mrc<TimerTask>, generation: u64) -> usize {
+    let entry = Some((task, generation));
+    match self.free.pop() {
+      Some(index) => {
+        self.entries[index] = entry;
+        index
+      }
+      None => {
+        self.entries.push(entry);
+        self.entries.len() - 1
+      }
+    }
+  }
+
+  /// Remove `task`'s entry at `index`. Does nothing if the entry is no
+  /// longer there because the bucket was taken since.
+  fn remove(&mut self, index: usize, task: &Mrc<TimerTask>) {
+    let Some(entry) = self.entries.get_mut(index) else {
+      return;
+    };
+    if !entry.as_ref().is_some_and(|(x, _)| Arc::ptr_eq(x, task)) {
+      return;
+    }
+
+    *entry = None;
+    self.free.push(index);
+    // Give the memory back once the bucket is empty.
+    if self.free.len() == self.entries.len() {
+      *self = Self::default();
+    }
+  }
+
+  /// Take every entry, leaving the bucket empty.
+  fn take(&mut self) -> impl Iterator<Item = (Mrc<TimerTask>, u64)> + use<> {
+    std::mem::take(self).entries.into_iter().flatten()
+  }
+
+  fn iter(&self) -> impl Iterator<Item = &(Mrc<TimerTask>, u64)> {
+    self.entries.iter().flatten()
   }
 }
 
 struct TimerWheel {
   /// Monotonically advances each `update()`. Written only by the
-  /// worker thread; read by `delay`/`ticker` to compute target buckets.
+  /// worker thread (or `Timer::advance`); read by `delay`/`ticker` to
+  /// compute target buckets.
   tick: AtomicU64,
-  /// One mutex per bucket. Registration and dispatch only contend when
-  /// they target the same bucket.
-  buckets: Box<[Mutex<Vec<Arc<Mutex<TimerTask>>>>; WHEEL_SIZE]>,
+  /// Duration of one tick.
+  resolution: Duration,
+  /// Driven by `Timer::advance` instead of a worker thread.
+  virtual_clock: bool,
+  /// Runs callbacks off the dispatching thread when set.
+  executor: Option<Arc<dyn TimerExecutor>>,
+  /// Set once shutdown starts. Registration then fails and no task is
+  /// re-armed.
+  closed: AtomicBool,
+  /// Fire, panic and lateness counters behind `Timer::stats`.
+  counters: Arc<TimerCounters>,
+  /// log2 of the number of buckets per level.
+  bits: u32,
+  /// Enough levels for the coarsest one to cover the top bits of a `u64`.
+  levels: usize,
+  /// `levels * 2^bits` buckets, level-major. Level 0 holds tasks due
+  /// within the next `2^bits` ticks; each bucket of level `n` spans
+  /// `2^(bits * n)` ticks.
+  buckets: Box<[Mutex<TimerBucket>]>,
 }
 
 impl TimerWheel {
-  fn new() -> Self {
+  /// `size` must be a power of two, as checked by `TimerConfig`.
+  fn new(
+    size: usize,
+    resolution: Duration,
+    virtual_clock: bool,
+    executor: Option<Arc<dyn TimerExecutor>>,
+    panic_hook: Option<TimerPanicHook>,
+  ) -> Self {
+    let bits = size.trailing_zeros();
+    let levels = ((u64::BITS - 1) / bits + 1) as usize;
+
     Self {
       tick: AtomicU64::new(0),
-      // Box the array so we don't put a ~64KB stack allocation on
-      // every Timer construction.
-      buckets: Box::new(std::array::from_fn(|_| Mutex::new(Vec::new()))),
+      resolution,
+      virtual_clock,
+      // `advance` promises callbacks have run when it returns.
+      executor: executor.filter(|_| !virtual_clock),
+      closed: AtomicBool::new(false),
+      counters: Arc::new(TimerCounters::new(panic_hook)),
+      bits,
+      levels,
+      buckets: (0..levels * size).map(|_| Mutex::default()).collect(),
     }
   }
 
-  fn bucket_of(tick: u64) -> usize {
-    (tick % WHEEL_SIZE as u64) as usize
+  fn mask(&self) -> usize {
+    (1 << self.bits) - 1
   }
 
-  fn lock_bucket(&self, bucket: usize) -> std::sync::MutexGuard<'_, Vec<Arc<Mutex<TimerTask>>>> {
-    self.buckets[bucket]
+  /// Convert a Duration to a tick count, rounding up so the task never
+  /// fires earlier than requested. Callers clamp zero-tick values to
+  /// one tick, so a sub-tick duration still schedules.
+  fn duration_to_ticks(&self, d: Duration) -> u64 {
+    let ticks = d.as_nanos().div_ceil(self.resolution.as_nanos());
+    u64::try_from(ticks).unwrap_or(u64::MAX)
+  }
+
+  fn ticks_to_duration(&self, ticks: u64) -> Duration {
+    let nanos = self.resolution.as_nanos().saturating_mul(ticks as u128);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+  }
+
+  /// Level and bucket for a task due at tick `at`, as seen from `now`.
+  ///
+  /// The level is the one whose bucket span covers the remaining delay,
+  /// so a task is cascaded exactly when the current tick reaches the
+  /// start of its bucket. Overdue tasks land in the current level-0
+  /// bucket.
+  fn slot_of(&self, at: u64, now: u64) -> (usize, usize) {
+    let at = at.max(now);
+    let level = match at - now {
+      0 => 0,
+      diff => (u64::BITS - 1 - diff.leading_zeros()) / self.bits,
+    };
+    let bucket = (at >> (level * self.bits)) as usize & self.mask();
+
+    (level as usize, bucket)
+  }
+
+  fn lock_bucket(&self, level: usize, bucket: usize) -> std::sync::MutexGuard<'_, TimerBucket> {
+    self.buckets[(level << self.bits) | bucket]
       .lock()
       .unwrap_or_else(|e| e.into_inner())
   }
 
-  fn delay<F>(&self, delay: u64, f: F) -> TimerHandle
+  /// Tick that registrations count their delay from.
+  ///
+  /// The worker dispatches tick `t` at the end of its period, so a
+  /// registration counts from the next tick to dispatch. A virtual
+  /// clock dispatches tick `t` as soon as `advance` reaches its start,
+  /// so it counts from the last dispatched one instead; that makes a
+  /// delay of `d` fire exactly when `advance` has covered `d`.
+  fn origin(&self) -> u64 {
+    self.tick.load(Ordering::Acquire) - self.virtual_clock as u64
+  }
+
+  // Every method below that takes `x: &mut TimerTask` is called with
+  // `task` locked. The lock order is always task, then bucket.
+
+  /// Put `task` into the bucket for its `delay`, as seen from `now`.
+  fn place(&self, task: &Mrc<TimerTask>, x: &mut TimerTask, now: u64) {
+    let (level, bucket) = self.slot_of(x.delay, now);
+    x.generation += 1;
+    let index = self
+      .lock_bucket(level, bucket)
+      .insert(task.clone(), x.generation);
+    x.slot = Some((level, bucket, index));
+  }
+
+  /// Take `task` out of its bucket, if it is in one.
+  fn unlink(&self, task: &Mrc<TimerTask>, x: &mut TimerTask) {
+    if let Some((level, bucket, index)) = x.slot.take() {
+      x.generation += 1;
+      self.lock_bucket(level, bucket).remove(index, task);
+    }
+  }
+
+  /// (Re-)schedule `task` to fire `ticks` from now.
+  fn arm(&self, task: &Mrc<TimerTask>, x: &mut TimerTask, ticks: u64) {
+    // Clamp to at least 1 tick. A delay of 0 would target the current
+    // bucket, which update() may have already processed this cycle.
+    // Use saturating_add so absurdly large delays cannot overflow.
+    let now = self.origin();
+    self.unlink(task, x);
+    x.delay = now.saturating_add(ticks.max(1));
+    self.place(task, x, now);
+  }
+
+  fn delay<F>(self: &Arc<Self>, delay: u64, f: F) -> Result<TimerHandle, TimerShutdown>
   where
     F: FnMut() + Send + 'static,
   {
-    // Clamp to at least 1 tick. A delay of 0 would target the current
-    // bucket, which update() may have already processed this cycle,
-    // forcing the task to wait an entire wheel rotation.
-    // Use saturating_add so absurdly large delays cannot overflow.
-    let fire_at = self.tick.load(Ordering::Acquire).saturating_add(delay.max(1));
+    self.register(TimerTask::new(delay, false, f).iMrc(), delay)
+  }
 
-    let task = TimerTask::new(fire_at, None, f).iMrc();
-    self.lock_bucket(Self::bucket_of(fire_at)).push(task.clone());
+  /// A one-shot task whose callback gets a handle to itself, to re-arm
+  /// it. The handle is rebuilt from weak references on every call so
+  /// the callback does not keep its own task alive.
+  fn delay_with<F>(self: &Arc<Self>, delay: u64, mut f: F) -> Result<TimerHandle, TimerShutdown>
+  where
+    F: FnMut(&TimerHandle) + Send + 'static,
+  {
+    let wheel = Arc::downgrade(self);
+    let task = Arc::new_cyclic(|task: &Weak<Mutex<TimerTask>>| {
+      let task = task.clone();
+      Mutex::new(TimerTask::new(delay, false, move || {
+        if let Some(task) = task.upgrade() {
+          f(&TimerHandle {
+            task,
+            wheel: wheel.clone(),
+          });
+        }
+      }))
+    });
 
-    TimerHandle(task)
+    self.register(task, delay)
   }
 
-  fn ticker<F>(&self, repeat: u64, f: F) -> TimerHandle
+  fn ticker<F>(self: &Arc<Self>, repeat: u64, f: F) -> Result<TimerHandle, TimerShutdown>
   where
     F: FnMut() + Send + 'static,
   {
     let repeat = repeat.max(1);
-    let fire_at = self.tick.load(Ordering::Acquire).saturating_add(repeat);
+    self.register(TimerTask::new(repeat, true, f).iMrc(), repeat)
+  }
+
+  /// A task whose `plan` decides on every wake-up. `reset` wakes it on
+  /// the next tick, where the plan simply re-arms for its deadline.
+  fn planned<F>(
+    self: &Arc<Self>,
+    first: PlanWait,
+    plan: TimerPlan,
+    f: F,
+  ) -> Result<TimerHandle, TimerShutdown>
+  where
+    F: FnMut() + Send + 'static,
+  {
+    let mut task = TimerTask::new(1, false, f);
+    task.plan = Some(plan);
+    let fire = self.duration_to_ticks(first.fire).max(1);
+    task.fire_at = self.origin().saturating_add(fire);
+    self.register(task.iMrc(), self.duration_to_ticks(first.wake))
+  }
+
+  fn register(
+    self: &Arc<Self>,
+    task: Mrc<TimerTask>,
+    ticks: u64,
+  ) -> Result<TimerHandle, TimerShutdown> {
+    if self.closed.load(Ordering::Acquire) {
+      return Err(TimerShutdown);
+    }
 
-    let task = TimerTask::new(fire_at, Some(repeat), f).iMrc();
-    self.lock_bucket(Self::bucket_of(fire_at)).push(task.clone());
+    task.with_mut(|x| self.arm(&task, x, ticks));
 
-    TimerHandle(task)
+    // Shutdown may have collected the pending tasks between the check
+    // above and the placement; back out rather than leave a task that
+    // will never fire.
+    if self.closed.load(Ordering::Acquire) {
+      // No hook yet: it is set once the handle is returned.
+      task.with_mut(|x| self.retire(&task, x));
+      return Err(TimerShutdown);
+    }
+
+    Ok(TimerHandle {
+      task,
+      wheel: Arc::downgrade(self),
+    })
+  }
+
+  /// Remove `task` for good, returning its `on_remove` hook for the
+  /// caller to run once the task lock is released.
+  fn retire(&self, task: &Mrc<TimerTask>, x: &mut TimerTask) -> Option<TimerRemoveHook> {
+    self.unlink(task, x);
+    x.mark_removed()
+  }
+
+  /// Retire every task still waiting, as the timer goes away.
+  fn retire_pending(&self) -> Vec<Mrc<TimerTask>> {
+    let tasks = self.pending();
+    for task in &tasks {
+      if let Some(hook) = task.with_mut(|x| self.retire(task, x)) {
+        hook();
+      }
+    }
+    tasks
+  }
+
+  /// Re-place the tasks of every coarse bucket whose span starts at
+  /// `current`. Higher levels go first so a task cascading from level
+  /// `n` into level `n - 1` is picked up again on the same tick if its
+  /// new bucket also starts here.
+  fn cascade(&self, current: u64) {
+    for level in (1..self.levels).rev() {
+      let shift = level as u32 * self.bits;
+      if current & ((1 << shift) - 1) != 0 {
+        continue;
+      }
+
+      let bucket = (current >> shift) as usize & self.mask();
+      let tasks = self.lock_bucket(level, bucket).take();
+
+      for (task, generation) in tasks {
+        task.with_mut(|x| {
+          // Stale entry: the task was rescheduled after this bucket
+          // was taken.
+          if x.generation != generation {
+            return;
+          }
+
+          // Removed tasks are dropped here instead of being carried
+          // down.
+          x.slot = None;
+          if !x.remove {
+            self.place(&task, x, current);
+          }
+        });
+      }
+    }
+  }
+
+  /// Hand a due callback to the executor.
+  ///
+  /// Fires of one task are counted in `queued` and drained by a single
+  /// job, so a ticker's callbacks never overlap and run in fire order
+  /// even on a multi-threaded executor.
+  fn submit(
+    executor: &dyn TimerExecutor,
+    counters: &Arc<TimerCounters>,
+    task: &Mrc<TimerTask>,
+    callback: TimerTaskCallback,
+  ) {
+    let first = task.with_mut(|x| {
+      x.queued += 1;
+      x.queued == 1
+    });
+    if !first {
+      return;
+    }
+
+    let task = task.clone();
+    let counters = counters.clone();
+    executor.execute(Box::new(move || {
+      loop {
+        let ok = TimerTask::invoke(&task, &callback, &counters);
+        let more = task.with_mut(|x| {
+          x.queued = if ok && !x.remove { x.queued - 1 } else { 0 };
+          x.queued > 0
+        });
+        if !more {
+          break;
+        }
+      }
+    }));
+  }
+
+  /// Tasks waiting in one bucket, skipping stale and removed entries.
+  /// The bucket is copied out first so no task lock is taken while
+  /// holding a bucket lock.
+  fn live(&self, level: usize, bucket: usize) -> Vec<Mrc<TimerTask>> {
+    let entries = self
+      .lock_bucket(level, bucket)
+      .iter()
+      .cloned()
+      .collect::<Vec<_>>();
+    entries
+      .into_iter()
+      .filter(|(task, generation)| task.with(|x| x.generation == *generation && !x.remove))
+      .map(|(task, _)| task)
+      .collect()
+  }
+
+  /// Count the live entries of every bucket.
+  fn occupancy(&self) -> Vec<Vec<usize>> {
+    let size = 1 << self.bits;
+    (0..self.levels)
+      .map(|level| {
+        (0..size)
+          .map(|bucket| self.live(level, bucket).len())
+          .collect()
+      })
+      .collect()
+  }
+
+  /// Entry slots allocated across all buckets, holes included.
+  #[cfg(test)]
+  fn footprint(&self) -> usize {
+    self
+      .buckets
+      .iter()
+      .map(|bucket| bucket.lock().unwrap().entries.len())
+      .sum()
+  }
+
+  /// Every task waiting in the wheel, soonest first.
+  fn pending(&self) -> Vec<Mrc<TimerTask>> {
+    let size = 1 << self.bits;
+    let mut tasks = (0..self.levels)
+      .flat_map(|level| (0..size).flat_map(move |bucket| self.live(level, bucket)))
+      .collect::<Vec<_>>();
+    tasks.sort_by_cached_key(|task| task.with(|x| x.delay));
+    tasks
   }
 
   fn update(&self) {
     let current = self.tick.load(Ordering::Acquire);
-    let bucket = Self::bucket_of(current);
 
-    // Only hold the bucket lock long enough to extract due tasks, then
+    self.cascade(current);
+
+    // Only hold the bucket lock long enough to take the due tasks, then
     // release so callbacks (which may take arbitrary time) don't block
     // concurrent delay/ticker registrations.
-    let tasks: Vec<Arc<Mutex<TimerTask>>> = {
-      let mut guard = self.lock_bucket(bucket);
-      guard.extract_if(.., |t| t.with(|x| x.delay == current)).collect()
-    };
+    let tasks = self.lock_bucket(0, current as usize & self.mask()).take();
 
-    for task in tasks {
-      // Decide whether to re-insert and where to schedule next.
-      // Returns Some(next_bucket) if the task should remain in the wheel.
-      let next_bucket = task.with_mut(|x| {
+    // Publish the next tick before dispatching, so registrations made
+    // from callbacks (or concurrently) count from it rather than from
+    // the tick being dispatched.
+    //
+    // saturating_add: the wheel becomes effectively frozen at u64::MAX,
+    // but that takes ~58 billion years at 100ms ticks. Only one thread
+    // runs update() at a time, so a plain load/store is sufficient.
+    self
+      .tick
+      .store(current.saturating_add(1), Ordering::Release);
+
+    for (task, generation) in tasks {
+      // Decide whether to fire and re-insert the task before running
+      // anything, so the callback runs without the task lock and sees
+      // its ticker already scheduled for the next cycle.
+      let mut hook = None;
+      let callback = task.with_mut(|x| {
+        if x.generation != generation {
+          return None;
+        }
+
+        x.slot = None;
         if x.remove {
           return None;
         }
 
-        if x.run {
-          // Isolate callback panics so they cannot kill the timer
-          // thread. A task that panics is marked for removal to avoid
-          // repeated panics on every fire.
-          let callback = &mut x.callback;
-          let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(
-            || callback(),
-          ));
-          if result.is_err() {
-            x.remove = true;
-            return None;
-          }
+        // Defensive: level 0 only holds tasks due within one rotation,
+        // so anything found here is due unless the wheel is corrupted.
+        if x.delay > current {
+          self.place(&task, x, current);
+          return None;
         }
 
         // Tickers stay in the wheel even when stopped, so `start()` can
         // resume them on the next repeat cycle. One-shot tasks that were
-        // stopped are dropped (their fire time has passed).
-        match x.repeat {
-          Some(repeat) => {
-            x.delay = current.saturating_add(repeat);
-            Some(Self::bucket_of(x.delay))
+        // stopped are dropped (their fire time has passed). Planned
+        // tasks are consulted even when stopped, for the same reason.
+        let (due, next) = match &mut x.plan {
+          Some(plan) => {
+            let (due, next) = plan();
+            let next = next.map(|wait| {
+              let fire = self.duration_to_ticks(wait.fire).max(1);
+              x.fire_at = current.saturating_add(fire);
+              self.duration_to_ticks(wait.wake)
+            });
+            (due, next)
+          }
+          None => (true, x.repeat.then_some(x.period)),
+        };
+
+        // Shutting down: nothing is re-armed, so draining terminates.
+        let next = next.filter(|_| !self.closed.load(Ordering::Acquire));
+
+        match next {
+          Some(ticks) => {
+            x.delay = current.saturating_add(ticks.max(1));
+            self.place(&task, x, current);
           }
-          None => None,
+          // A ticker or plan that stops here is done for good.
+          None if x.repeat || x.plan.is_some() => hook = x.mark_removed(),
+          None => {}
         }
+
+        (x.run && due).then(|| {
+          x.fired += 1;
+          x.callback.clone()
+        })
       });
 
-      if let Some(bucket) = next_bucket {
-        self.lock_bucket(bucket).push(task);
+      if let Some(callback) = callback {
+        match &self.executor {
+          Some(executor) => Self::submit(executor.as_ref(), &self.counters, &task, callback),
+          None => {
+            TimerTask::invoke(&task, &callback, &self.counters);
+          }
+        }
+      }
+      if let Some(hook) = hook {
+        hook();
       }
     }
-
-    // saturating_add: the wheel becomes effectively frozen at u64::MAX,
-    // but that takes ~58 billion years at 100ms ticks. The worker
-    // thread is the only writer, so a plain load/store is sufficient.
-    let next = current.saturating_add(1);
-    self.tick.store(next, Ordering::Release);
   }
 }
 
 /// Shared timer handle. Cloning is cheap (a single `Arc` bump) and
 /// safe to send across threads. The worker thread is stopped and joined
-/// when the **last** clone is dropped.
+/// when the **last** clone is dropped, and tasks still waiting are
+/// removed, as by [`Timer::shutdown`].
 #[derive(Clone)]
 pub struct Timer(Arc<TimerInner>);
 
 struct TimerInner {
   wheel: Arc<TimerWheel>,
   shutdown: Arc<AtomicBool>,
-  /// `None` once the worker thread has been joined.
+  /// `None` once the worker thread has been joined, or for a virtual
+  /// clock which never has one.
   worker: Mutex<Option<JoinHandle<()>>>,
+  /// `Some` when the wheel is driven by `advance`.
+  clock: Option<Arc<VirtualClock>>,
 }
 
-impl Timer {
-  /// 0.1s
-  const TICK: Duration = Duration::from_millis(100);
+/// Virtual time of a timer created with `TimerConfig::virtual_clock`.
+struct VirtualClock {
+  start: Instant,
+  /// Wall-clock time at `start`.
+  wall_start: DateTime<Local>,
+  /// Nanoseconds since `start`. Atomic so callbacks running inside
+  /// `advance` can still read `Timer::now`.
+  elapsed: AtomicU64,
+  /// Serializes `advance` calls.
+  advancing: Mutex<()>,
+}
+
+/// Wall-clock source for tasks scheduled by calendar time. Follows the
+/// virtual clock when there is one.
+#[derive(Clone)]
+pub(crate) struct WallClock(Option<Arc<VirtualClock>>);
+
+impl WallClock {
+  pub(crate) fn now(&self) -> DateTime<Local> {
+    match &self.0 {
+      Some(clock) => {
+        clock.wall_start + TimeDelta::from_std(clock.elapsed()).unwrap_or(TimeDelta::MAX)
+      }
+      None => Local::now(),
+    }
+  }
+}
+
+impl VirtualClock {
+  fn elapsed(&self) -> Duration {
+    Duration::from_nanos(self.elapsed.load(Ordering::Acquire))
+  }
+
+  fn set_elapsed(&self, elapsed: Duration) {
+    let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+    self.elapsed.store(nanos, Ordering::Release);
+  }
+}
 
+impl Timer {
+  /// Create a timer with the default [`TimerConfig`]: a 100ms tick and
+  /// 64 buckets per level.
   pub fn new() -> Self {
-    let wheel = Arc::new(TimerWheel::new());
+    Self::with_config(TimerConfig::default()).expect("default TimerConfig is valid")
+  }
+
+  /// Create a timer driven by [`advance`](Self::advance) instead of a
+  /// worker thread. Shorthand for a default config with
+  /// `virtual_clock(true)`.
+  pub fn new_virtual() -> Self {
+    Self::with_config(TimerConfig::builder().virtual_clock(true).build())
+      .expect("default TimerConfig is valid")
+  }
+
+  /// Create a timer with custom tick resolution, wheel size or thread
+  /// name. Fails if the settings are inconsistent or the worker thread
+  /// cannot be spawned.
+  pub fn with_config(config: TimerConfig) -> Result<Self, TimerConfigError> {
+    config.validate()?;
+
+    let tick = config.tick();
+    let virtual_clock = config.virtual_clock.unwrap_or(false);
+    let wheel = Arc::new(TimerWheel::new(
+      config.wheel_size(),
+      tick,
+      virtual_clock,
+      config.executor.clone(),
+      config.panic_hook.clone(),
+    ));
     let shutdown = Arc::new(AtomicBool::new(false));
 
+    if virtual_clock {
+      // Dispatch tick 0 up front: virtual time starts at its beginning.
+      wheel.update();
+
+      return Ok(Self(Arc::new(TimerInner {
+        wheel,
+        shutdown,
+        worker: Mutex::new(None),
+        clock: Some(Arc::new(VirtualClock {
+          start: Instant::now(),
+          wall_start: Local::now(),
+          elapsed: AtomicU64::new(0),
+          advancing: Mutex::new(()),
+        })),
+      })));
+    }
+
     let worker = {
       #[clone(wheel, shutdown)]
-      let handle = thread::spawn(move || {
-        // Schedule against absolute deadlines so update() execution time
-        // does not accumulate as drift on top of each sleep.
-        let mut next = Instant::now() + Self::TICK;
-        while !shutdown.load(Ordering::Acquire) {
-          let now = Instant::now();
-          if next > now {
-            // Sleep in short slices so shutdown is observed promptly
-            // even when the next tick is far away.
-            let remaining = next - now;
-            let slice = remaining.min(Self::TICK);
-            thread::sleep(slice);
-            continue;
-          }
-          next += Self::TICK;
+      let handle = thread::Builder::new()
+        .name(config.thread_name().to_owned())
+        .spawn(move || {
+          // Schedule against absolute deadlines so update() execution
+          // time does not accumulate as drift on top of each sleep.
+          let mut next = Instant::now() + tick;
+          while !shutdown.load(Ordering::Acquire) {
+            let now = Instant::now();
+            if next > now {
+              // Sleep in short slices so shutdown is observed promptly
+              // even when the next tick is far away.
+              let remaining = next - now;
+              let slice = remaining.min(tick);
+              thread::sleep(slice);
+              continue;
+            }
+            wheel.counters.record_lateness(now - next);
+            next += tick;
 
-          wheel.update();
-        }
-      });
+            wheel.update();
+          }
+        })
+        .map_err(TimerConfigError::Spawn)?;
       handle
     };
 
-    Self(Arc::new(TimerInner {
+    Ok(Self(Arc::new(TimerInner {
       wheel,
       shutdown,
       worker: Mutex::new(Some(worker)),
-    }))
+      clock: None,
+    })))
   }
 
-  /// Stop the worker thread and wait for it to exit.
+  /// Move a virtual clock forward by `d`, synchronously dispatching
+  /// every tick whose start it passes. Callbacks run on the calling
+  /// thread before `advance` returns, even if `TimerConfig::executor`
+  /// is set, so they must not call `advance` themselves;
+  /// [`now`](Self::now) reports the start of the tick they run on.
+  ///
+  /// Sub-tick remainders carry over, so ten `advance(10ms)` calls on a
+  /// 100ms tick dispatch exactly one tick.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use std::sync::Arc;
+  /// use std::sync::atomic::{AtomicBool, Ordering};
+  /// use std::time::Duration;
+  /// use libu_timer::Timer;
   ///
-  /// Pending tasks are dropped without firing. After `shutdown`, the
-  /// timer no longer ticks; further `delay`/`ticker` calls will still
-  /// register tasks but they will never fire. Safe to call from any
-  /// clone and to call multiple times.
+  /// let timer = Timer::new_virtual();
+  /// let fired = Arc::new(AtomicBool::new(false));
+  ///
+  /// let flag = fired.clone();
+  /// let _handle = timer
+  ///   .delay(Duration::from_secs(5), move || flag.store(true, Ordering::SeqCst))
+  ///   .unwrap();
+  ///
+  /// timer.advance(Duration::from_millis(4900));
+  /// assert!(!fired.load(Ordering::SeqCst));
+  ///
+  /// timer.advance(Duration::from_millis(100));
+  /// assert!(fired.load(Ordering::SeqCst));
+  /// ```
+  ///
+  /// # Panics
+  ///
+  /// Panics if the timer was not created with a virtual clock.
+  pub fn advance(&self, d: Duration) {
+    let clock = self
+      .0
+      .clock
+      .as_ref()
+      .expect("Timer::advance requires a virtual clock");
+
+    let _guard = clock.advancing.lock().unwrap_or_else(|e| e.into_inner());
+    let end = clock.elapsed().saturating_add(d);
+    let target = self.duration_to_whole_ticks(end);
+
+    loop {
+      let tick = self.0.wheel.tick.load(Ordering::Acquire);
+      if tick > target {
+        break;
+      }
+
+      let start = self.0.wheel.ticks_to_duration(tick);
+      clock.set_elapsed(clock.elapsed().max(start));
+      self.0.wheel.update();
+    }
+
+    clock.set_elapsed(end);
+  }
+
+  /// Current time as seen by this timer: `Instant::now()` normally, the
+  /// creation instant plus everything passed to `advance` for a virtual
+  /// clock.
+  pub fn now(&self) -> Instant {
+    match &self.0.clock {
+      Some(clock) => clock.start + clock.elapsed(),
+      None => Instant::now(),
+    }
+  }
+
+  /// Snapshot of the timer's load and health; see [`TimerStats`].
+  ///
+  /// Takes every bucket lock in turn, so avoid calling it in a tight
+  /// loop on a timer with a large wheel.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use std::time::Duration;
+  /// use libu_timer::Timer;
+  ///
+  /// let timer = Timer::new_virtual();
+  /// let _handle = timer.delay(Duration::from_secs(1), || {}).unwrap();
+  /// assert_eq!(timer.stats().tasks, 1);
+  ///
+  /// timer.advance(Duration::from_secs(1));
+  /// let stats = timer.stats();
+  /// assert_eq!((stats.tasks, stats.fired), (0, 1));
+  /// ```
+  pub fn stats(&self) -> TimerStats {
+    let wheel = &self.0.wheel;
+    let buckets = wheel.occupancy();
+
+    TimerStats {
+      tasks: buckets.iter().flatten().sum(),
+      buckets,
+      ..wheel.counters.snapshot(wheel.virtual_clock)
+    }
+  }
+
+  /// Like [`delay`](Self::delay), but the callback gets the task's own
+  /// handle so it can re-arm itself.
+  pub(crate) fn delay_with<F>(&self, delay: Duration, f: F) -> Result<TimerHandle, TimerShutdown>
+  where
+    F: FnMut(&TimerHandle) + Send + 'static,
+  {
+    let ticks = self.0.wheel.duration_to_ticks(delay);
+    self.0.wheel.delay_with(ticks, f)
+  }
+
+  /// Wall clock matching [`now`](Self::now).
+  pub(crate) fn wall_clock(&self) -> WallClock {
+    WallClock(self.0.clock.clone())
+  }
+
+  /// Register a task that consults `plan` each time it comes due; see
+  /// `TimerPlan`. The first wake-up is `first` from now.
+  pub(crate) fn planned<P, F>(
+    &self,
+    first: PlanWait,
+    plan: P,
+    f: F,
+  ) -> Result<TimerHandle, TimerShutdown>
+  where
+    P: FnMut() -> (bool, Option<PlanWait>) + Send + 'static,
+    F: FnMut() + Send + 'static,
+  {
+    self.0.wheel.planned(first, plan.iBox(), f)
+  }
+
+  /// Shut down with [`ShutdownPolicy::Drop`]: stop the worker thread,
+  /// wait for it to exit and drop pending tasks without firing them.
   pub fn shutdown(&self) {
+    self.shutdown_with(ShutdownPolicy::Drop);
+  }
+
+  /// Stop the timer, dealing with pending tasks as `policy` says, and
+  /// return the ones that will never fire, soonest first.
+  ///
+  /// From the moment this is called, registrations fail with
+  /// [`TimerShutdown`] and no task is re-armed: tickers, cron and retry
+  /// tasks fire at most once more. Returned tasks are removed. Safe to
+  /// call from any clone and to call multiple times, but not from a
+  /// timer callback.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use std::time::Duration;
+  /// use libu_timer::{ShutdownPolicy, Timer};
+  ///
+  /// let timer = Timer::new_virtual();
+  /// let soon = timer.delay(Duration::from_secs(1), || {}).unwrap();
+  /// let late = timer.delay(Duration::from_secs(60), || {}).unwrap();
+  ///
+  /// let dropped = timer.shutdown_with(ShutdownPolicy::Drain(Duration::from_secs(5)));
+  /// assert_eq!(soon.fire_count(), 1);
+  /// assert_eq!(dropped, vec![late]);
+  ///
+  /// assert!(timer.delay(Duration::from_secs(1), || {}).is_err());
+  /// ```
+  pub fn shutdown_with(&self, policy: ShutdownPolicy) -> Vec<TimerHandle> {
+    let wheel = &self.0.wheel;
+    wheel.closed.store(true, Ordering::Release);
+
+    match policy {
+      ShutdownPolicy::Drop => {}
+      ShutdownPolicy::FireNow => {
+        self.stop_worker();
+
+        for task in wheel.pending() {
+          // Stopped tasks stay behind and are returned below.
+          let fire = task.with_mut(|x| {
+            x.run.then(|| {
+              x.fired += 1;
+              (x.callback.clone(), wheel.retire(&task, x))
+            })
+          });
+
+          // Fire before running the hook, so waiters see the fire.
+          if let Some((callback, hook)) = fire {
+            TimerTask::invoke(&task, &callback, &wheel.counters);
+            if let Some(hook) = hook {
+              hook();
+            }
+          }
+        }
+      }
+      ShutdownPolicy::Drain(timeout) => match &self.0.clock {
+        Some(clock) => {
+          let end = clock.elapsed().saturating_add(timeout);
+          while clock.elapsed() < end && !wheel.pending().is_empty() {
+            self.advance(wheel.resolution.min(end - clock.elapsed()));
+          }
+        }
+        None => {
+          let deadline = Instant::now() + timeout;
+          while !wheel.pending().is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+              break;
+            }
+            thread::sleep(wheel.resolution.min(deadline - now));
+          }
+        }
+      },
+    }
+
+    self.stop_worker();
+
+    wheel
+      .retire_pending()
+      .into_iter()
+      .map(|task| TimerHandle {
+        task,
+        wheel: Arc::downgrade(wheel),
+      })
+      .collect()
+  }
+
+  /// Handles of every task waiting in the wheel, soonest first. Tasks
+  /// whose callback is running are not included.
+  pub fn pending(&self) -> Vec<TimerHandle> {
+    let wheel = &self.0.wheel;
+    wheel
+      .pending()
+      .into_iter()
+      .map(|task| TimerHandle {
+        task,
+        wheel: Arc::downgrade(wheel),
+      })
+      .collect()
+  }
+
+  fn stop_worker(&self) {
     self.0.shutdown.store(true, Ordering::Release);
     if let Some(handle) = self
       .0
@@ -309,33 +1298,35 @@ impl Timer {
     }
   }
 
-  pub fn delay<F>(&self, delay: Duration, f: F) -> TimerHandle
+  pub fn delay<F>(&self, delay: Duration, f: F) -> Result<TimerHandle, TimerShutdown>
   where
     F: FnMut() + Send + 'static,
   {
-    let ticks = Self::duration_to_ticks(delay);
+    let ticks = self.0.wheel.duration_to_ticks(delay);
     self.0.wheel.delay(ticks, f)
   }
 
-  pub fn ticker<F>(&self, repeat: Duration, f: F) -> TimerHandle
+  pub fn ticker<F>(&self, repeat: Duration, f: F) -> Result<TimerHandle, TimerShutdown>
   where
     F: FnMut() + Send + 'static,
   {
-    let ticks = Self::duration_to_ticks(repeat);
+    let ticks = self.0.wheel.duration_to_ticks(repeat);
     self.0.wheel.ticker(ticks, f)
   }
 
-  /// Convert a Duration to a tick count, rounding up so the task never
-  /// fires earlier than requested. The wheel itself clamps zero-tick
-  /// values to one tick, so a sub-tick duration still schedules.
-  fn duration_to_ticks(d: Duration) -> u64 {
-    let tick_nanos = Self::TICK.as_nanos();
-    let d_nanos = d.as_nanos();
-    let ticks = d_nanos.div_ceil(tick_nanos);
+  /// Number of ticks that fit entirely in `d`, rounding down.
+  fn duration_to_whole_ticks(&self, d: Duration) -> u64 {
+    let ticks = d.as_nanos() / self.0.wheel.resolution.as_nanos();
     u64::try_from(ticks).unwrap_or(u64::MAX)
   }
 }
 
+impl Default for Timer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl Drop for TimerInner {
   fn drop(&mut self) {
     // Only reached when the last Timer clone is dropped, since Timer
@@ -344,5 +1335,295 @@ impl Drop for TimerInner {
     if let Some(handle) = self.worker.lock().unwrap_or_else(|e| e.into_inner()).take() {
       let _ = handle.join();
     }
+
+    // Nothing will dispatch the tasks left behind; remove them so their
+    // waiters are released instead of hanging.
+    self.wheel.closed.store(true, Ordering::Release);
+    self.wheel.retire_pending();
+  }
+}
+
+/// What [`Timer::shutdown_with`] does with tasks still in the wheel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownPolicy {
+  /// Drop them without firing.
+  Drop,
+  /// Fire every running task once, right away, on the calling thread.
+  FireNow,
+  /// Keep ticking until every task has fired for the last time or the
+  /// timeout has passed, then drop the rest. On a virtual clock the
+  /// timeout is virtual time, advanced by `shutdown_with` itself.
+  Drain(Duration),
+}
+
+/// Returned when registering a task on a timer that has been shut
+/// down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerShutdown;
+
+impl std::fmt::Display for TimerShutdown {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "timer has been shut down")
+  }
+}
+
+impl std::error::Error for TimerShutdown {}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicU32, Ordering};
+  use std::time::Duration;
+
+  use super::*;
+
+  fn counter() -> (Arc<AtomicU32>, impl FnMut() + Send + 'static) {
+    let count = Arc::new(AtomicU32::new(0));
+    let fired = count.clone();
+    (count, move || {
+      fired.fetch_add(1, Ordering::SeqCst);
+    })
+  }
+
+  #[test]
+  fn reset_pushes_the_deadline_back() {
+    let timer = Timer::new_virtual();
+    let (count, f) = counter();
+    let handle = timer.delay(Duration::from_secs(10), f).unwrap();
+
+    // Many resets leave stale entries behind; only the last one fires.
+    for _ in 0..9 {
+      timer.advance(Duration::from_secs(1));
+      handle.reset();
+    }
+    assert_eq!(timer.stats().tasks, 1);
+
+    timer.advance(Duration::from_millis(9900));
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    timer.advance(Duration::from_millis(100));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    timer.advance(Duration::from_secs(60));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert_eq!(handle.remaining(), None);
+  }
+
+  #[test]
+  fn reset_rearms_a_fired_one_shot() {
+    let timer = Timer::new_virtual();
+    let (count, f) = counter();
+    let handle = timer.delay(Duration::from_secs(1), f).unwrap();
+
+    timer.advance(Duration::from_secs(1));
+    handle.reset();
+    assert_eq!(handle.remaining(), Some(Duration::from_secs(1)));
+
+    timer.advance(Duration::from_secs(1));
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+  }
+
+  #[test]
+  fn remove_cancels_without_firing() {
+    let timer = Timer::new_virtual();
+    let (count, f) = counter();
+    let handle = timer.delay(Duration::from_secs(3600), f).unwrap();
+    let (ticks, g) = counter();
+    let ticker = timer.ticker(Duration::from_secs(1), g).unwrap();
+
+    handle.remove();
+    assert!(handle.is_removed());
+    assert_eq!(timer.stats().tasks, 1);
+
+    timer.advance(Duration::from_secs(2));
+    ticker.remove();
+    timer.advance(Duration::from_secs(3600));
+
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert_eq!(ticks.load(Ordering::SeqCst), 2);
+    assert_eq!(timer.stats().tasks, 0);
+  }
+
+  #[test]
+  fn tasks_cascade_down_to_fire_on_time() {
+    let timer = Timer::new_virtual();
+    // 100ms ticks and 64 buckets: these land on levels 0, 1, 2 and 3.
+    let delays = [3, 600, 3600, 30 * 3600].map(Duration::from_secs);
+    let counts = delays.map(|delay| {
+      let (count, f) = counter();
+      let handle = timer.delay(delay, f).unwrap();
+      (delay, count, handle)
+    });
+
+    let mut elapsed = Duration::ZERO;
+    for (delay, count, handle) in &counts {
+      timer.advance(*delay - Duration::from_millis(100) - elapsed);
+      assert_eq!(count.load(Ordering::SeqCst), 0, "{delay:?} fired early");
+      assert_eq!(handle.remaining(), Some(Duration::from_millis(100)));
+
+      timer.advance(Duration::from_millis(100));
+      assert_eq!(count.load(Ordering::SeqCst), 1, "{delay:?} did not fire");
+      elapsed = *delay;
+    }
+  }
+
+  #[test]
+  fn mass_resets_in_one_bucket() {
+    let timer = Timer::new_virtual();
+    let (count, _) = counter();
+    let handles = (0..1000)
+      .map(|_| {
+        let count = count.clone();
+        timer
+          .delay(Duration::from_secs(3600), move || {
+            count.fetch_add(1, Ordering::SeqCst);
+          })
+          .unwrap()
+      })
+      .collect::<Vec<_>>();
+
+    for _ in 0..5 {
+      timer.advance(Duration::from_secs(600));
+      handles.iter().for_each(TimerHandle::reset);
+    }
+    // Odd ones are cancelled after their last reset.
+    handles
+      .iter()
+      .skip(1)
+      .step_by(2)
+      .for_each(TimerHandle::remove);
+    assert_eq!(timer.stats().tasks, 500);
+
+    timer.advance(Duration::from_millis(3600 * 1000 - 100));
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    timer.advance(Duration::from_secs(7200));
+    assert_eq!(count.load(Ordering::SeqCst), 500);
+    assert_eq!(timer.stats().tasks, 0);
+  }
+
+  fn poll<F: std::future::Future + Unpin>(f: &mut F) -> std::task::Poll<F::Output> {
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    std::pin::Pin::new(f).poll(&mut cx)
+  }
+
+  #[test]
+  fn shutdown_releases_waiters() {
+    use std::task::Poll;
+
+    use futures_core::Stream;
+
+    use crate::{Backoff, RetryError};
+
+    let timer = Timer::new_virtual();
+    let mut sleep = timer.sleep(Duration::from_secs(10)).unwrap();
+    let mut interval = timer.interval(Duration::from_secs(1)).unwrap();
+    let tick = timer.every(Duration::from_secs(1)).unwrap();
+    let retry = timer
+      .retry(
+        Backoff::fixed(Duration::from_secs(5), Duration::ZERO),
+        || Err::<(), _>("down"),
+      )
+      .unwrap();
+
+    timer.advance(Duration::from_secs(1));
+    assert_eq!(poll(&mut sleep), Poll::Pending);
+    assert!(!retry.is_finished());
+
+    timer.shutdown();
+    assert_eq!(poll(&mut sleep), Poll::Ready(Err(TimerShutdown)));
+    // The fire from before shutdown is still delivered, then it ends.
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    let mut interval = std::pin::Pin::new(&mut interval);
+    assert_eq!(interval.as_mut().poll_next(&mut cx), Poll::Ready(Some(())));
+    assert_eq!(interval.as_mut().poll_next(&mut cx), Poll::Ready(None));
+    assert_eq!(tick.recv(), Ok(1));
+    assert!(tick.recv().is_err());
+    assert_eq!(retry.wait(), Err(RetryError::Cancelled));
+  }
+
+  #[test]
+  fn dropping_the_timer_releases_waiters() {
+    use std::task::Poll;
+
+    let timer = Timer::new();
+    let mut sleep = timer.sleep(Duration::from_secs(3600)).unwrap();
+    let after = timer.after(Duration::from_secs(3600)).unwrap();
+    assert_eq!(poll(&mut sleep), Poll::Pending);
+
+    drop(timer);
+    assert!(sleep.handle().is_removed());
+    assert_eq!(poll(&mut sleep), Poll::Ready(Err(TimerShutdown)));
+    assert!(after.recv().is_err());
+  }
+
+  #[test]
+  fn virtual_clock_runs_callbacks_inline_despite_an_executor() {
+    let timer = Timer::with_config(
+      TimerConfig::builder()
+        .virtual_clock(true)
+        .executor(Arc::new(TimerPool::new(2)))
+        .build(),
+    )
+    .unwrap();
+    let caller = thread::current().id();
+    let ran_on = Arc::new(Mutex::new(None));
+
+    let slot = ran_on.clone();
+    let _handle = timer
+      .delay(Duration::from_secs(1), move || {
+        *slot.lock().unwrap() = Some(thread::current().id());
+      })
+      .unwrap();
+
+    timer.advance(Duration::from_secs(1));
+    assert_eq!(*ran_on.lock().unwrap(), Some(caller));
+  }
+
+  #[test]
+  fn resets_do_not_grow_the_wheel() {
+    let timer = Timer::new_virtual();
+    let handles = (0..100)
+      .map(|_| timer.delay(Duration::from_secs(3600), || {}).unwrap())
+      .collect::<Vec<_>>();
+
+    for i in 0..200_000 {
+      if i % 1000 == 0 {
+        timer.advance(Duration::from_secs(1));
+      }
+      handles[i % handles.len()].reset();
+    }
+    assert_eq!(timer.stats().tasks, 100);
+    assert!(timer.0.wheel.footprint() <= 2 * handles.len());
+
+    for handle in &handles {
+      handle.remove();
+    }
+    assert_eq!(timer.0.wheel.footprint(), 0);
+  }
+
+  #[test]
+  fn cron_remaining_counts_to_the_next_run() {
+    let timer = Timer::new_virtual();
+    let schedule: Cron = "0 30 4 * * *".parse().unwrap();
+    let now = timer.wall_clock().now();
+    let next = schedule.next_after(&now).unwrap();
+    let handle = timer.cron(schedule, || {}).unwrap();
+
+    let expected = (next - now).to_std().unwrap();
+    let remaining = handle.remaining().unwrap();
+    assert!(remaining.abs_diff(expected) <= Duration::from_millis(200));
+
+    // Still counting to the run, not to the next minute's wake-up.
+    timer.advance(Duration::from_secs(120));
+    let remaining = handle.remaining().unwrap();
+    assert!(remaining.abs_diff(expected - Duration::from_secs(120)) <= Duration::from_millis(200));
+  }
+
+  #[test]
+  fn cron_rejects_schedules_that_never_run() {
+    assert!("0 0 0 30 2 *".parse::<Cron>().is_err());
+    assert!("0 0 0 31 4,6,9,11 *".parse::<Cron>().is_err());
+    assert!("0 0 0 29 2 *".parse::<Cron>().is_ok());
+    // Either day field may match.
+    assert!("0 0 0 30 2 MON".parse::<Cron>().is_ok());
   }
 }
@@ -12,7 +12,9 @@
 //! | `T: Sized` | [`void`] | Suppress must_use warnings |
 //! | `str` | [`to_dur`] | Parse string to Duration |
 //! | `Vec<T>` | [`remove_if`] | Remove elements by condition |
+//! | `dyn Any + Send` | [`panic_msg`] | Message of a panic payload |
 
+use std::any::Any;
 use std::time::Duration;
 
 use extend::ext;
@@ -180,4 +182,31 @@ impl<T, F: Fn(&T) -> bool> Vec<T> {
 
     removed
   }
-}
\ No newline at end of file
+}
+
+/// Message of a panic payload
+///
+/// Returns the message a panic was raised with, as caught by
+/// `std::panic::catch_unwind` or returned by `JoinHandle::join`. Payloads
+/// that are neither `&str` nor `String` give `"Box<dyn Any>"`.
+///
+/// # Example
+///
+/// ```rust
+/// use libu_trait::PanicMsg;
+///
+/// let payload = std::panic::catch_unwind(|| panic!("boom {}", 1)).unwrap_err();
+/// assert_eq!(payload.panic_msg(), "boom 1");
+/// ```
+#[ext(pub, name = PanicMsg)]
+impl dyn Any + Send {
+  fn panic_msg(&self) -> &str {
+    match self.downcast_ref::<&str>() {
+      Some(msg) => msg,
+      None => match self.downcast_ref::<String>() {
+        Some(msg) => msg.as_str(),
+        None => "Box<dyn Any>",
+      },
+    }
+  }
+}
@@ -1,6 +1,10 @@
 #![allow(non_snake_case)]
 #![allow(unused)]
 
+extern crate self as libu;
+
+mod select;
+
 pub use libu_chan::*;
 pub use libu_derive::*;
 pub use libu_log::*;
@@ -8,6 +12,7 @@ pub use libu_macro::*;
 pub use libu_point::*;
 pub use libu_timer::*;
 pub use libu_trait::*;
+pub use select::*;
 
 mod test {
   use super::*;
@@ -0,0 +1,133 @@
+//! Glue between the `select!` macro and the channel types it accepts.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use flume::{Receiver, RecvError, Selector, Sender};
+
+/// Something a `select!` receive arm can wait on: a flume `Receiver`, a
+/// [`Chan`](crate::Chan), a [`WatchReceiver`](crate::WatchReceiver) or
+/// a [`TimerRecv`](crate::TimerRecv).
+///
+/// A `Chan` arm behaves like [`Chan::recv`](crate::Chan::recv): once the
+/// peer has closed its sending half and the queue is drained, the
+/// handler gets `Err(RecvError::Disconnected)`.
+pub trait SelectRecv {
+  type Item;
+
+  fn select_rx(&self) -> &Receiver<Self::Item>;
+
+  /// Add this receive arm to `selector`. By default it waits on
+  /// [`select_rx`](Self::select_rx) alone.
+  fn select_recv<'a, U, F>(&'a self, selector: Selector<'a, U>, handler: F) -> Selector<'a, U>
+  where
+    F: FnMut(Result<Self::Item, RecvError>) -> U + 'a,
+  {
+    selector.recv(self.select_rx(), handler)
+  }
+}
+
+/// Something a `select!` `send` arm can send on: a flume `Sender` or a
+/// [`Chan`](crate::Chan).
+///
+/// Sending through `select!` bypasses [`Chan::close_send`](crate::Chan::close_send).
+pub trait SelectSend {
+  type Item;
+
+  fn select_tx(&self) -> &Sender<Self::Item>;
+}
+
+impl<T: SelectRecv + ?Sized> SelectRecv for &T {
+  type Item = T::Item;
+
+  fn select_rx(&self) -> &Receiver<T::Item> {
+    (**self).select_rx()
+  }
+
+  fn select_recv<'a, U, F>(&'a self, selector: Selector<'a, U>, handler: F) -> Selector<'a, U>
+  where
+    F: FnMut(Result<T::Item, RecvError>) -> U + 'a,
+  {
+    (**self).select_recv(selector, handler)
+  }
+}
+
+impl<T: SelectSend + ?Sized> SelectSend for &T {
+  type Item = T::Item;
+
+  fn select_tx(&self) -> &Sender<T::Item> {
+    (**self).select_tx()
+  }
+}
+
+impl<T> SelectRecv for Receiver<T> {
+  type Item = T;
+
+  fn select_rx(&self) -> &Receiver<T> {
+    self
+  }
+}
+
+impl<T> SelectSend for Sender<T> {
+  type Item = T;
+
+  fn select_tx(&self) -> &Sender<T> {
+    self
+  }
+}
+
+impl<S, R> SelectRecv for libu_chan::Chan<S, R> {
+  type Item = R;
+
+  fn select_rx(&self) -> &Receiver<R> {
+    self.rx()
+  }
+
+  fn select_recv<'a, U, F>(&'a self, selector: Selector<'a, U>, handler: F) -> Selector<'a, U>
+  where
+    F: FnMut(Result<R, RecvError>) -> U + 'a,
+  {
+    // Also wake on the peer's close. Only one arm fires per wait, so
+    // both can share the handler.
+    let handler = Rc::new(RefCell::new(handler));
+    let on_close = handler.clone();
+
+    selector
+      .recv(self.rx(), move |msg| (handler.borrow_mut())(msg))
+      .recv(self.close_signal(), move |_| {
+        // Drain what was sent before the close.
+        let msg = self.rx().try_recv().map_err(|_| RecvError::Disconnected);
+        (on_close.borrow_mut())(msg)
+      })
+  }
+}
+
+impl<S, R> SelectSend for libu_chan::Chan<S, R> {
+  type Item = S;
+
+  fn select_tx(&self) -> &Sender<S> {
+    self.tx()
+  }
+}
+
+impl<T> SelectRecv for libu_chan::WatchReceiver<T> {
+  type Item = u64;
+
+  fn select_rx(&self) -> &Receiver<u64> {
+    self.rx()
+  }
+}
+
+impl SelectRecv for libu_timer::TimerRecv {
+  type Item = u64;
+
+  fn select_rx(&self) -> &Receiver<u64> {
+    self.rx()
+  }
+}
+
+/// Paths used by `select!`'s expansion. Not public API.
+#[doc(hidden)]
+pub mod __private {
+  pub use flume::Selector;
+}
@@ -0,0 +1,145 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libu::*;
+
+#[test]
+fn recv_arm_takes_the_ready_channel() {
+  let (a, b) = channel::<u32, u32>();
+  let (c, d) = channel::<u32, u32>();
+  c.send(7).unwrap();
+
+  let got = select! [
+    b => |msg| ("b", msg),
+    d => |msg| ("d", msg),
+  ];
+  assert_eq!(got, ("d", Ok(7)));
+  drop(a);
+}
+
+#[test]
+fn send_arm_sends_when_there_is_room() {
+  let (a, b) = channel_bounded::<u32, ()>(1, 0);
+
+  let sent = select! [send(a, 5) => |res| res.is_ok()];
+  assert!(sent);
+  assert_eq!(b.recv(), Ok(5));
+
+  // Full: the `send` arm never becomes ready.
+  a.send(6).unwrap();
+  let sent = select! [
+    send(a, 7) => |res| res.is_ok(),
+    default => || false,
+  ];
+  assert!(!sent);
+  assert_eq!(b.try_recv(), Ok(6));
+}
+
+#[test]
+fn timeout_arm_runs_when_nothing_is_ready() {
+  let (_a, b) = channel::<(), u32>();
+  let start = Instant::now();
+
+  let got = select! [
+    b => |msg| msg.ok(),
+    timeout(Duration::from_millis(20)) => || None,
+  ];
+  assert_eq!(got, None);
+  assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn deadline_arm_runs_when_nothing_is_ready() {
+  let (_a, b) = channel::<(), u32>();
+  let deadline = Instant::now() + Duration::from_millis(20);
+
+  let got = select! [
+    b => |msg| msg.ok(),
+    deadline(deadline) => || None,
+  ];
+  assert_eq!(got, None);
+  assert!(Instant::now() >= deadline);
+}
+
+#[test]
+fn default_arm_runs_only_when_nothing_is_ready() {
+  let (a, b) = channel::<u32, ()>();
+
+  let poll = || {
+    select! [
+      b => |msg| msg.ok(),
+      default => || None,
+    ]
+  };
+  assert_eq!(poll(), None);
+  a.send(1).unwrap();
+  assert_eq!(poll(), Some(1));
+}
+
+#[test]
+fn malformed_arms_fail_to_compile() {
+  trybuild::TestCases::new().compile_fail("tests/ui/select_*.rs");
+}
+
+#[test]
+fn chan_arm_sees_the_peer_close() {
+  let (a, b) = channel::<u32, ()>();
+  a.send(1).unwrap();
+
+  let waiter = thread::spawn(move || {
+    let mut got = Vec::new();
+    loop {
+      let msg = select! [
+        b => |msg| msg,
+        timeout(Duration::from_secs(5)) => || panic!("the close was not seen"),
+      ];
+      match msg {
+        Ok(n) => got.push(n),
+        Err(e) => return (got, e),
+      }
+    }
+  });
+
+  thread::sleep(Duration::from_millis(20));
+  a.send(2).unwrap();
+  a.close_send();
+
+  // Queued messages come first; `a` is still alive.
+  assert_eq!(
+    waiter.join().unwrap(),
+    (vec![1, 2], RecvError::Disconnected)
+  );
+}
+
+#[test]
+fn one_select_waits_on_a_chan_a_ticker_and_a_watch() {
+  #[derive(Debug, PartialEq)]
+  enum Event {
+    Msg(u32),
+    Tick(u64),
+    Config(&'static str),
+  }
+
+  let timer = Timer::new_virtual();
+  let tick = timer.every(Duration::from_secs(1)).unwrap();
+  let (config_tx, config) = watch("v1");
+  let (a, b) = channel::<u32, ()>();
+
+  let next = || {
+    select! [
+      b => |msg| Some(Event::Msg(msg.unwrap())),
+      tick => |fired| Some(Event::Tick(fired.unwrap())),
+      config => |_| Some(Event::Config(*config.borrow_and_update())),
+      default => || None,
+    ]
+  };
+
+  assert_eq!(next(), None);
+  a.send(1).unwrap();
+  assert_eq!(next(), Some(Event::Msg(1)));
+  timer.advance(Duration::from_secs(1));
+  assert_eq!(next(), Some(Event::Tick(1)));
+  config_tx.send("v2");
+  assert_eq!(next(), Some(Event::Config("v2")));
+  assert_eq!(next(), None);
+}
@@ -0,0 +1,7 @@
+fn main() {
+  let (_a, b) = libu::channel::<u32, u32>();
+  libu::select! [
+    b => |msg| msg.ok(),
+    default(1) => || None,
+  ];
+}
@@ -0,0 +1,8 @@
+fn main() {
+  let (_a, b) = libu::channel::<u32, u32>();
+  let (_c, d) = libu::channel::<u32, u32>();
+  libu::select! [
+    b => |msg| msg.ok()
+    d => |msg| msg.ok()
+  ];
+}
@@ -0,0 +1,4 @@
+fn main() {
+  let (_a, b) = libu::channel::<u32, u32>();
+  libu::select![b];
+}
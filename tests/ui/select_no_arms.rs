@@ -0,0 +1,3 @@
+fn main() {
+  libu::select! [default => || ()];
+}
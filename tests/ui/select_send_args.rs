@@ -0,0 +1,4 @@
+fn main() {
+  let (a, _b) = libu::channel::<u32, u32>();
+  libu::select! [send(a) => |res| res.is_ok()];
+}
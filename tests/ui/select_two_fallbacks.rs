@@ -0,0 +1,8 @@
+fn main() {
+  let (_a, b) = libu::channel::<u32, u32>();
+  libu::select! [
+    b => |msg| msg.ok(),
+    timeout(std::time::Duration::from_secs(1)) => || None,
+    default => || None,
+  ];
+}